@@ -0,0 +1,170 @@
+/* Opening books store precomputed actions for the first few positions.
+
+A book is a text file with one entry per line: a canonical position string
+(see game::State::position_string) followed by the column to play, e.g.
+
+    -/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- w B2
+
+Lines starting with '#' are comments. Positions are stored in canonical form
+(see symmetry::canonical_form), so one entry covers all symmetric positions.
+As the canonical form depends on the symmetries of the victory structure, a
+book should only be used with the structure it was generated for. */
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use ai::{AIBox, Constructor, StatelessAI};
+use game;
+use game::Position2;
+use symmetry::canonical_form;
+
+#[derive(Debug)]
+pub struct OpeningBook {
+    // Maps canonical position strings to the action for this position.
+    entries: HashMap<String, Position2>,
+}
+
+impl OpeningBook {
+    pub fn new() -> Self {
+        OpeningBook { entries: HashMap::new() }
+    }
+    pub fn load(path: &str, structure: Arc<game::Structure>) -> Result<Self, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("Can't read the opening book '{}': {}", path, err))?;
+        OpeningBook::parse(&text, structure)
+    }
+    // Entries which aren't in canonical form are stored like insert does.
+    pub fn parse(text: &str, structure: Arc<game::Structure>) -> Result<Self, String> {
+        let mut book = OpeningBook::new();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.len() != 3 {
+                return Err(format!(
+                    "Line {} of the opening book should read '<columns> <side> <action>'.",
+                    line_number + 1
+                ));
+            }
+            let position = format!("{} {}", tokens[0], tokens[1]);
+            let state = game::State::from_position_string(structure.clone(), &position)
+                .map_err(|err| format!("Line {} of the opening book: {}", line_number + 1, err))?;
+            let action = tokens[2]
+                .parse::<Position2>()
+                .map_err(|err| format!("Line {} of the opening book: {}", line_number + 1, err))?;
+            book.insert(&state, action);
+        }
+
+        Ok(book)
+    }
+    pub fn save(&self, path: &str, header: &str) -> Result<(), String> {
+        // Sorting keeps the files diffable.
+        let mut lines: Vec<String> = self.entries
+            .iter()
+            .map(|(position, action)| format!("{} {}", position, action))
+            .collect();
+        lines.sort();
+
+        let mut text = format!("# {}\n", header);
+        for line in lines {
+            text += &line;
+            text += "\n";
+        }
+
+        File::create(path)
+            .and_then(|mut file| file.write_all(text.as_bytes()))
+            .map_err(|err| format!("Can't write the opening book '{}': {}", path, err))
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn insert(&mut self, state: &game::State, action: Position2) {
+        let (canonical, symmetry) = canonical_form(state);
+        self.entries.insert(
+            canonical.position_string(),
+            symmetry.apply_position2(action),
+        );
+    }
+    pub fn lookup(&self, state: &game::State) -> Option<Position2> {
+        let (canonical, symmetry) = canonical_form(state);
+        let action = *self.entries.get(&canonical.position_string())?;
+        let action = symmetry.inverse().apply_position2(action);
+        // A book for a different structure may suggest nonsense.
+        if state.column_full(action) {
+            None
+        } else {
+            Some(action)
+        }
+    }
+}
+
+// Builds a book covering every position up to `depth` actions after `start`.
+// Each position is analysed by the given AI, so this is meant to run offline
+// with a strong (and slow) AI.
+pub fn generate(start: &game::State, depth: u8, constructor: &Constructor) -> OpeningBook {
+    let ai = AIBox::new(constructor.clone());
+    let mut book = OpeningBook::new();
+    let mut frontier = vec![start.clone()];
+
+    for ply in 0..depth {
+        let mut next_frontier = vec![];
+
+        for state in frontier {
+            if !state.victory_state.active() || book.lookup(&state).is_some() {
+                continue;
+            }
+            // Only the canonical representative needs to be expanded.
+            let (canonical, _) = canonical_form(&state);
+            let action = ai.action(&canonical);
+            book.insert(&canonical, action);
+            println!(
+                "Ply {}, entry {}: {} {}",
+                ply,
+                book.len(),
+                canonical.position_string(),
+                action
+            );
+
+            for next_action in canonical.legal_actions() {
+                let mut next_state = canonical.clone();
+                next_state.execute(next_action);
+                next_frontier.push(next_state);
+            }
+        }
+
+        frontier = next_frontier;
+    }
+
+    book
+}
+
+// Plays from the opening book and asks the fallback AI once the book runs out.
+pub struct BookAI {
+    book: Arc<OpeningBook>,
+    fallback: Box<AIBox>,
+}
+
+impl BookAI {
+    pub fn new(book: Arc<OpeningBook>, fallback: AIBox) -> Self {
+        BookAI {
+            book,
+            fallback: Box::new(fallback),
+        }
+    }
+}
+
+impl StatelessAI for BookAI {
+    fn action(&self, state: &game::State) -> Position2 {
+        match self.book.lookup(state) {
+            Some(action) => action,
+            None => self.fallback.action(state),
+        }
+    }
+}
@@ -1,10 +1,38 @@
 
 use ai::StatelessAI;
+use ai::tactics::{forced_action, Tactics};
+use ai::value::{column_value, SideValue};
 
 use game;
 use game::{Position2, VictoryState, VictoryStats};
 
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, ThreadRng};
+
+use std::str::FromStr;
+
+// How the actions of a playout are chosen.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Policy {
+    // Every legal action is equally likely.
+    Uniform,
+    // Win or block if possible, otherwise uniform.
+    WinOrBlock,
+    // Win if possible, otherwise draw actions with a probability given by
+    // their heuristic value, see value::column_value.
+    Heuristic,
+}
+
+impl FromStr for Policy {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Policy, ()> {
+        match s {
+            "uniform" => Ok(Policy::Uniform),
+            "winblock" => Ok(Policy::WinOrBlock),
+            "heuristic" => Ok(Policy::Heuristic),
+            _ => Err(()),
+        }
+    }
+}
 
 
 // Pure Monte Carlo AI
@@ -13,16 +41,27 @@ use rand::{thread_rng, Rng};
 // about the value of each action.
 pub struct MonteCarloAI {
     endurance: usize, // How many random games am I allowed to play each turn?
+    tactics: Tactics,
+    policy: Policy,
 }
 
 impl MonteCarloAI {
-    pub fn new(endurance: usize) -> MonteCarloAI {
-        MonteCarloAI { endurance: endurance }
+    pub fn new(endurance: usize, tactics: Tactics, policy: Policy) -> MonteCarloAI {
+        MonteCarloAI {
+            endurance,
+            tactics,
+            policy,
+        }
     }
 }
 
 impl StatelessAI for MonteCarloAI {
     fn action(&self, state: &game::State) -> Position2 {
+        if self.tactics.at_root() {
+            if let Some(action) = forced_action(state) {
+                return action;
+            }
+        }
         let my_color = state.current_color;
         let legal_actions: Vec<Position2> = state.legal_actions().collect();
         let endurance_per_action = self.endurance / (legal_actions.len() as usize);
@@ -34,7 +73,13 @@ impl StatelessAI for MonteCarloAI {
             .map(|action| {
                 let mut new_state = state.clone();
                 new_state.execute(*action);
-                let value = monte_carlo_judgement(&new_state, my_color, endurance_per_action);
+                let value = monte_carlo_judgement(
+                    &new_state,
+                    my_color,
+                    endurance_per_action,
+                    self.policy,
+                    self.tactics.in_playouts(),
+                );
                 (action, value)
             })
             .max_by_key(|&(_, value)| value)
@@ -44,8 +89,14 @@ impl StatelessAI for MonteCarloAI {
     }
 }
 
-fn monte_carlo_judgement(state: &game::State, my_color: game::Color, amount: usize) -> i32 {
-    let stats = random_playout_sample(state, amount);
+fn monte_carlo_judgement(
+    state: &game::State,
+    my_color: game::Color,
+    amount: usize,
+    policy: Policy,
+    heavy: bool,
+) -> i32 {
+    let stats = random_playout_sample(state, amount, policy, heavy);
     if my_color == game::Color::White {
         return stats.white - stats.black;
     } else {
@@ -53,21 +104,88 @@ fn monte_carlo_judgement(state: &game::State, my_color: game::Color, amount: usi
     }
 }
 
-pub fn random_playout(mut state: game::State) -> VictoryState {
+// Heavy playouts always win or block if possible, see ai::tactics. The policy
+// only decides the remaining actions.
+pub fn random_playout(state: game::State, policy: Policy, heavy: bool) -> VictoryState {
+    recorded_playout(state, policy, heavy).0
+}
+
+// Like random_playout, but also returns the actions which were played.
+pub fn recorded_playout(
+    mut state: game::State,
+    policy: Policy,
+    heavy: bool,
+) -> (VictoryState, Vec<Position2>) {
     let mut rng = thread_rng();
+    let mut actions = vec![];
     while state.victory_state == VictoryState::Undecided {
-        let legal_actions: Vec<Position2> = state.legal_actions().collect();
-        let action = *rng.choose(&legal_actions).unwrap();
+        let forced = if heavy { forced_action(&state) } else { None };
+        let action = forced.unwrap_or_else(|| match policy {
+            Policy::Uniform => uniform_action(&state, &mut rng),
+            Policy::WinOrBlock => {
+                forced_action(&state).unwrap_or_else(|| uniform_action(&state, &mut rng))
+            }
+            Policy::Heuristic => heuristic_action(&state, &mut rng),
+        });
         state.execute(action);
+        actions.push(action);
+    }
+    (state.victory_state, actions)
+}
+
+fn uniform_action(state: &game::State, rng: &mut ThreadRng) -> Position2 {
+    let legal_actions: Vec<Position2> = state.legal_actions().collect();
+    *rng.choose(&legal_actions).unwrap()
+}
+
+// Blocking actions get a large weight, actions which let the opponent win
+// right away are only played if there is nothing else left.
+fn heuristic_action(state: &game::State, rng: &mut ThreadRng) -> Position2 {
+    use self::SideValue::{LastMissingPiece, Heuristic, DirectLoss};
+
+    let mut weighted_actions = vec![];
+    let mut total_weight = 0;
+
+    for column in state.legal_actions() {
+        let (white_value, black_value) = column_value(state, column).unwrap();
+        let (my_value, their_value) = match state.current_color {
+            game::Color::White => (white_value, black_value),
+            game::Color::Black => (black_value, white_value),
+        };
+        let weight = match (my_value, their_value) {
+            (LastMissingPiece, _) => return column,
+            (_, LastMissingPiece) => 1000,
+            (Heuristic(value), _) => value.max(0) + 1,
+            (DirectLoss, _) => 0,
+        };
+        total_weight += weight;
+        weighted_actions.push((column, weight));
+    }
+
+    if total_weight == 0 {
+        return uniform_action(state, rng);
+    }
+
+    let mut target = rng.gen_range(0, total_weight);
+    for (column, weight) in weighted_actions {
+        if target < weight {
+            return column;
+        }
+        target -= weight;
     }
-    state.victory_state
+    unreachable!("The target is always smaller than the total weight.")
 }
 
 
-pub fn random_playout_sample(state: &game::State, number: usize) -> VictoryStats {
+pub fn random_playout_sample(
+    state: &game::State,
+    number: usize,
+    policy: Policy,
+    heavy: bool,
+) -> VictoryStats {
     let mut statics = game::VictoryStats::new();
     for _ in 0..number {
-        let result = random_playout(state.clone());
+        let result = random_playout(state.clone(), policy, heavy);
         // TODO: Use the function provided by game::VictoryState
         match result {
             game::VictoryState::Win { winner, .. } => {
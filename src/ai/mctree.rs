@@ -9,14 +9,36 @@ Expansion: unless L ends the game with a win/loss for either player, create chil
 Simulation: play a random playout from node C.
 Backpropagation: use the result of the playout to update information in the nodes.
 
+Three enhancements can be switched on, see Enhancements:
+
+RAVE: Each node also keeps all-moves-as-first statistics. Every action the
+player to move plays later in the same simulation counts as if it was played
+right away. These statistics are available much earlier than the real ones
+and are mixed into the selection while a child has only few simulations.
+Progressive bias: The heuristic value of an action (see value::column_value)
+is added to the selection value. Its influence fades with more simulations.
+Progressive widening: Only the heuristically best children are considered at
+first, more children are unpruned as the node is visited more often. A node
+with n simulations considers the best ceil(widening * sqrt(n + 1)) children.
+
+The tree also works as an MCTS-Solver: Terminal nodes are proven wins, and
+proofs propagate upwards. A node is lost if one of its children is won by the
+opponent, and won if all of its children are lost by the opponent. Proven
+nodes are not simulated any more and proven losses are never selected.
+
 */
 
 use rand::{thread_rng, Rng};
 
 use ai::StatelessAI;
+use ai::mc::Policy;
+use ai::tactics::{forced_action, Tactics};
+use ai::value::{column_value, SideValue};
 use game;
 use game::Position2;
 
+use std::cmp::Reverse;
+use std::fmt;
 use std::sync::mpsc::{channel, Sender};
 use threadpool::ThreadPool;
 
@@ -24,6 +46,49 @@ use threadpool::ThreadPool;
 pub struct MCTreeAI {
     endurance: usize,
     exploration: f32,
+    tactics: Tactics,
+    policy: Policy,
+    enhancements: Enhancements,
+}
+
+// Each enhancement is switched off by None, otherwise it carries its parameter.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Enhancements {
+    // The number of simulations at which real and AMAF statistics are
+    // weighted equally.
+    pub rave: Option<f32>,
+    // The weight of the heuristic value.
+    pub bias: Option<f32>,
+    // A node with n simulations considers ceil(widening * sqrt(n + 1))
+    // children, so a new node starts with one.
+    pub widening: Option<f32>,
+}
+
+impl Enhancements {
+    // Switches on the enhancement described by a command line token like
+    // "rave" or "bias=0.5".
+    pub fn enable(&mut self, token: &str) -> Result<(), String> {
+        let mut parts = token.splitn(2, '=');
+        let name = parts.next().unwrap();
+        let (option, default) = match name {
+            "rave" => (&mut self.rave, 300.0),
+            "bias" => (&mut self.bias, 1.0),
+            "widening" => (&mut self.widening, 1.0),
+            _ => return Err(format!("Unknown enhancement '{}'.", name)),
+        };
+        let value = match parts.next() {
+            Some(value) => value.parse::<f32>().map_err(|_| {
+                format!("The parameter of '{}' needs to be a number.", name)
+            })?,
+            None => default,
+        };
+        *option = Some(value);
+        Ok(())
+    }
+    // Priors are only calculated if an enhancement needs them.
+    fn needs_priors(&self) -> bool {
+        self.bias.is_some() || self.widening.is_some()
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -40,69 +105,283 @@ struct VecTree {
 }
 
 impl VecTree {
-    fn new(capacity: usize, state: &game::State) -> Self {
+    fn new(capacity: usize, state: &game::State, enhancements: Enhancements) -> Self {
         let mut storage = Vec::with_capacity(capacity);
-        storage.push(Node::new(None, state));
+        storage.push(Node::new(None, state, enhancements));
         VecTree { storage }
     }
     fn robust_move(&self) -> Position2 {
         // Select the action, which was played most often. Apparently this
         // is more robust than using the action with the best win ratio.
+        // Proven wins are played right away, proven losses only if every
+        // other action loses.
         let ref root = self.storage[0];
 
+        let proven_wins: Vec<usize> = (0..16)
+            .filter(|&i| self.child_proof(root, i) == Some(Proof::Win))
+            .collect();
+        if let Some(&choosen_position) = thread_rng().choose(&proven_wins) {
+            return Position2(choosen_position as u8);
+        }
+
+        let (lost, open): (Vec<usize>, Vec<usize>) = (0..16)
+            .filter(|&i| self.child_proof(root, i).is_some())
+            .partition(|&i| self.child_proof(root, i) == Some(Proof::Loss));
+        // With few simulations or with progressive widening, some legal
+        // actions may never have been tried. They are still better than a
+        // proven loss.
+        let unexpanded: Vec<usize> = (0..16)
+            .filter(|&i| root.children[i] == ChildRef::NotYetExpanded)
+            .collect();
+
+        let candidates = if !open.is_empty() {
+            self.most_simulated(root, open)
+        } else if !unexpanded.is_empty() {
+            unexpanded
+        } else {
+            self.most_simulated(root, lost)
+        };
+
+        let choosen_position = *thread_rng().choose(&candidates).unwrap();
+
+        // Finally, we got the best move - return it to play it.
+        Position2(choosen_position as u8)
+    }
+    // The expanded children among the given ones with the most simulations.
+    fn most_simulated(&self, node: &Node, children: Vec<usize>) -> Vec<usize> {
         let mut most_robust = vec![];
         let mut most_simulations = 0;
 
-        for i in 0..16 {
-            match root.children[i] {
-                ChildRef::Expanded(child_index) => {
-                    let ref child = self.storage[child_index.0];
-                    if child.simulation_count > most_simulations {
-                        most_robust = vec![i];
-                        most_simulations = child.simulation_count;
-                    } else if child.simulation_count == most_simulations {
-                        most_robust.push(i);
-                    } else {
-                        // This child is worse than a previously seen child.
+        for i in children {
+            if let ChildRef::Expanded(child_index) = node.children[i] {
+                let ref child = self.storage[child_index.0];
+                if child.simulation_count > most_simulations {
+                    most_robust = vec![i];
+                    most_simulations = child.simulation_count;
+                } else if child.simulation_count == most_simulations {
+                    most_robust.push(i);
+                } else {
+                    // This child is worse than a previously seen child.
+                }
+            }
+        }
+        most_robust
+    }
+    // The proof of an expanded child, None otherwise.
+    fn child_proof(&self, node: &Node, i: usize) -> Option<Proof> {
+        match node.children[i] {
+            ChildRef::Expanded(child_index) => Some(self.storage[child_index.0].proof),
+            _ => None,
+        }
+    }
+    fn solved(&self) -> bool {
+        self.storage[0].proof != Proof::Unknown
+    }
+    // Follows the most visited children, starting with the given action.
+    fn principal_variation(&self, child_index: Index, action: usize) -> Vec<Position2> {
+        let mut variation = vec![Position2(action as u8)];
+        let mut node = &self.storage[child_index.0];
+
+        loop {
+            let mut best: Option<(usize, Index)> = None;
+            for i in 0..16 {
+                if let ChildRef::Expanded(index) = node.children[i] {
+                    let visits = self.storage[index.0].simulation_count;
+                    let better = match best {
+                        Some((_, best_index)) => {
+                            visits > self.storage[best_index.0].simulation_count
+                        }
+                        None => true,
+                    };
+                    if better {
+                        best = Some((i, index));
                     }
                 }
-                _ => {}
+            }
+            match best {
+                Some((i, index)) => {
+                    variation.push(Position2(i as u8));
+                    node = &self.storage[index.0];
+                }
+                None => return variation,
             }
         }
+    }
+    fn analysis(&self, best: Position2) -> Analysis {
+        let root = &self.storage[0];
+        let mut columns = vec![];
 
-        let choosen_position = *thread_rng().choose(&most_robust).unwrap();
+        for i in 0..16 {
+            if let ChildRef::Expanded(child_index) = root.children[i] {
+                let child = &self.storage[child_index.0];
+                let value = match child.proof {
+                    Proof::Win => 1.0,
+                    Proof::Loss => -1.0,
+                    Proof::Unknown => {
+                        child.win_count as f32 / child.simulation_count.max(1) as f32
+                    }
+                };
+                columns.push(ColumnStats {
+                    column: Position2(i as u8),
+                    visits: child.simulation_count,
+                    value,
+                    principal_variation: self.principal_variation(child_index, i),
+                });
+            }
+        }
 
-        // Finally, we got the best move - return it to play it.
-        Position2(choosen_position as u8)
+        Analysis { columns, best }
+    }
+}
+
+// What the search found out about one column of the root.
+#[derive(Clone, Debug)]
+pub struct ColumnStats {
+    pub column: Position2,
+    pub visits: usize,
+    // The mean result for the player to move, between -1 (loss) and 1 (win).
+    pub value: f32,
+    // The expected continuation, starting with this column.
+    pub principal_variation: Vec<Position2>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    // Only the columns which were searched at all.
+    pub columns: Vec<ColumnStats>,
+    // The action the AI plays.
+    pub best: Position2,
+}
+
+impl Analysis {
+    // The value of each column, indexed like Position2.
+    pub fn overlay(&self) -> [Option<f32>; 16] {
+        let mut overlay = [None; 16];
+        for stats in &self.columns {
+            overlay[stats.column.0 as usize] = Some(stats.value);
+        }
+        overlay
+    }
+}
+
+// Lists the columns with the most visits first.
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut columns: Vec<&ColumnStats> = self.columns.iter().collect();
+        columns.sort_by_key(|stats| Reverse(stats.visits));
+
+        for stats in columns {
+            let variation: Vec<String> = stats
+                .principal_variation
+                .iter()
+                .map(|action| format!("{}", action))
+                .collect();
+            writeln!(
+                f,
+                "{} {:7} visits, value {:+.3}, variation {}",
+                stats.column,
+                stats.visits,
+                stats.value,
+                variation.join(" ")
+            )?;
+        }
+        write!(f, "Best action: {}", self.best)
     }
 }
 
+// Proofs are given from the perspective of the player who moved into the
+// node, just like the win_count.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Proof {
+    Unknown,
+    Win,
+    Loss,
+}
+
 struct Node {
     // The win_count counts wins - losses and can be negative.
     win_count: isize,
     simulation_count: usize,
+    proof: Proof,
+
+    // All-moves-as-first statistics for the children, from the perspective
+    // of the player to move in this node.
+    amaf_win_count: [isize; 16],
+    amaf_simulation_count: [usize; 16],
+
+    // Heuristic values of the children between -1 and 1, the legal actions
+    // sorted by them, best first. Both are empty without bias and widening.
+    priors: [f32; 16],
+    order: Vec<usize>,
 
     parent: Option<Index>,
+    // The action which leads from the parent to this node.
+    action: Option<Position2>,
     children: [ChildRef; 16],
 }
 
 impl Node {
-    fn new(parent_index: Option<Index>, state: &game::State) -> Self {
+    fn new(
+        parent: Option<(Index, Position2)>,
+        state: &game::State,
+        enhancements: Enhancements,
+    ) -> Self {
         let mut children = [ChildRef::NotYetExpanded; 16];
         for i in 0..16 {
             if state.column_height[i] == 4 {
                 children[i] = ChildRef::IllegalMove;
             }
         }
+
+        let mut priors = [0.0; 16];
+        let mut order = vec![];
+        if enhancements.needs_priors() && state.victory_state.active() {
+            for column in state.legal_actions() {
+                priors[column.0 as usize] = prior(state, column);
+                order.push(column.0 as usize);
+            }
+            order.sort_by(|a, b| priors[*b].partial_cmp(&priors[*a]).unwrap());
+        }
+
+        // Only the player who just moved can have completed a subset.
+        let proof = match state.victory_state {
+            game::VictoryState::Win { .. } => Proof::Win,
+            _ => Proof::Unknown,
+        };
+
         Node {
             win_count: 0,
             simulation_count: 0,
-            parent: parent_index,
+            proof,
+            amaf_win_count: [0; 16],
+            amaf_simulation_count: [0; 16],
+            priors,
+            order,
+            parent: parent.map(|(index, _)| index),
+            action: parent.map(|(_, action)| action),
             children,
         }
     }
 }
 
+// The heuristic value of an action for the player to move.
+fn prior(state: &game::State, column: Position2) -> f32 {
+    use self::SideValue::{LastMissingPiece, Heuristic, DirectLoss};
+
+    let (white_value, black_value) = column_value(state, column).unwrap();
+    let (my_value, their_value) = match state.current_color {
+        game::Color::White => (white_value, black_value),
+        game::Color::Black => (black_value, white_value),
+    };
+    match (my_value, their_value) {
+        (LastMissingPiece, _) => 1.0,
+        (_, LastMissingPiece) => 0.8,
+        // Ordinary actions stay below blocking actions.
+        (Heuristic(value), _) => 0.5 * (value as f32 / 20.0).tanh(),
+        (DirectLoss, _) => -1.0,
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum ChildRef {
     IllegalMove,
@@ -116,49 +395,80 @@ impl VecTree {
         node_index: Index,
         mut state: game::State,
         exploration: f32,
+        enhancements: Enhancements,
     ) -> (Index, game::State) {
         use std::f32;
 
-        if !state.victory_state.active() {
+        if !state.victory_state.active() || self.storage[node_index.0].proof != Proof::Unknown {
             return (node_index, state);
         }
 
-        let mut candidates: Vec<usize> = vec![];
+        let candidates = self.candidates(node_index, enhancements);
+
+        let unexpanded: Vec<usize> = candidates
+            .iter()
+            .cloned()
+            .filter(|&i| self.storage[node_index.0].children[i] == ChildRef::NotYetExpanded)
+            .collect();
+        if !unexpanded.is_empty() {
+            // With progressive widening the children are unpruned in the
+            // order of their heuristic value, otherwise at random.
+            let choosen_position = if enhancements.widening.is_some() {
+                unexpanded[0]
+            } else {
+                *thread_rng().choose(&unexpanded).unwrap()
+            };
+            return self.initialize_child(node_index, choosen_position, state, enhancements);
+        }
+
+        let mut best_candidates: Vec<usize> = vec![];
         let mut best_value: f32 = f32::NEG_INFINITY;
 
-        for i in 0..16 {
-            match self.storage[node_index.0].children[i] {
-                ChildRef::IllegalMove => {}
-                ChildRef::NotYetExpanded => {
-                    return self.initialize_random_child(node_index, i, state);
-                }
-                ChildRef::Expanded(child_index) => {
-                    let ref node = self.storage[node_index.0];
-                    let ref child = self.storage[child_index.0];
-
-                    let exploitation_value = child.win_count as f32 / child.simulation_count as f32;
-                    let exploration_value = exploration *
-                        f32::sqrt(
-                            f32::ln(node.simulation_count as f32) / child.simulation_count as f32,
-                        );
-
-                    let value = exploration_value + exploitation_value;
-
-                    if value > best_value {
-                        best_value = value;
-                        candidates = vec![i];
-                    } else if value == best_value {
-                        candidates.push(i);
-                    } else {
-                        // Do nothing, this child doesn't qualify for the random draw.
+        for i in candidates {
+            if let ChildRef::Expanded(child_index) = self.storage[node_index.0].children[i] {
+                let ref node = self.storage[node_index.0];
+                let ref child = self.storage[child_index.0];
+                let child_simulations = child.simulation_count as f32;
+
+                let mut exploitation_value = child.win_count as f32 / child_simulations;
+                if let Some(equivalence) = enhancements.rave {
+                    let amaf_simulations = node.amaf_simulation_count[i] as f32;
+                    if amaf_simulations > 0.0 {
+                        let amaf_value = node.amaf_win_count[i] as f32 / amaf_simulations;
+                        let beta = f32::sqrt(equivalence / (3.0 * child_simulations + equivalence));
+                        exploitation_value = (1.0 - beta) * exploitation_value + beta * amaf_value;
                     }
                 }
+
+                let exploration_value = exploration *
+                    f32::sqrt(f32::ln(node.simulation_count as f32) / child_simulations);
+
+                let bias_value = match enhancements.bias {
+                    Some(bias) => bias * node.priors[i] / (child_simulations + 1.0),
+                    None => 0.0,
+                };
+
+                // The simulation of a new child may still be running.
+                let value = if child.simulation_count == 0 {
+                    f32::INFINITY
+                } else {
+                    exploration_value + exploitation_value + bias_value
+                };
+
+                if value > best_value {
+                    best_value = value;
+                    best_candidates = vec![i];
+                } else if value == best_value {
+                    best_candidates.push(i);
+                } else {
+                    // Do nothing, this child doesn't qualify for the random draw.
+                }
             }
         }
 
         // TODO: We can only sensibly unwrap, if we make sure that
         // the game is still ongoing at this moment.
-        let choosen_position = *thread_rng().choose(&candidates).unwrap_or_else(|| {
+        let choosen_position = *thread_rng().choose(&best_candidates).unwrap_or_else(|| {
             panic!("Trying to choose a best child when no children are avaliable.")
         });
 
@@ -168,7 +478,7 @@ impl VecTree {
             self.storage[node_index.0].children[choosen_position]
         {
 
-            self.select_best(child_index, state, exploration)
+            self.select_best(child_index, state, exploration, enhancements)
 
         } else {
             // The choosen_position is drawn from indices
@@ -176,64 +486,128 @@ impl VecTree {
             panic!("The choosen child somehow isn't expanded.");
         }
     }
-    // Selects and initializes an uninitialized move.
-    fn initialize_random_child(
+    // The children which may be selected. Proven losses are never selected.
+    // As long as the node is not proven, there is always a child left.
+    fn candidates(&self, node_index: Index, enhancements: Enhancements) -> Vec<usize> {
+        let node = &self.storage[node_index.0];
+        let open = |&i: &usize| self.child_proof(node, i) != Some(Proof::Loss);
+        match enhancements.widening {
+            Some(widening) => {
+                let visits = node.simulation_count as f32 + 1.0;
+                let allowed = (widening * visits.sqrt()).ceil().max(1.0) as usize;
+                node.order.iter().cloned().filter(open).take(allowed).collect()
+            }
+            None => (0..16)
+                .filter(|&i| node.children[i] != ChildRef::IllegalMove)
+                .filter(open)
+                .collect(),
+        }
+    }
+    // Initializes the child for the given action.
+    fn initialize_child(
         &mut self,
         node_index: Index,
-        first_uninitialized_child_index: usize,
+        choosen_position: usize,
         mut state: game::State,
+        enhancements: Enhancements,
     ) -> (Index, game::State) {
         let new_index = Index(self.storage.len());
+        // Adding the child might reallocate the whole vector, so the parent
+        // is only accessed by index.
+        self.storage[node_index.0].children[choosen_position] = ChildRef::Expanded(new_index);
 
-        let choosen_position = {
-            // To satisfy borrowck, I first mutate the parent node itself,
-            // then drop the mutable reference to it and add the new child
-            // to the vector which also contains the node.
-            // Borrowck rightfully complains about this, because adding a child
-            // might reallocate the whole vector, rendering the node pointer invalid.
-            let ref mut node = self.storage[node_index.0];
-
-            let mut uninitialized_children = vec![first_uninitialized_child_index];
-            for i in (first_uninitialized_child_index + 1)..16 {
-                if node.children[i] == ChildRef::NotYetExpanded {
-                    uninitialized_children.push(i);
-                }
-            }
-
-            let choosen_position = *thread_rng().choose(&uninitialized_children).unwrap();
-
-            node.children[choosen_position] = ChildRef::Expanded(new_index);
-            choosen_position
-        };
-
-        state.execute(Position2(choosen_position as u8));
-        self.storage.push(Node::new(Some(node_index), &state));
+        let action = Position2(choosen_position as u8);
+        state.execute(action);
+        self.storage.push(Node::new(Some((node_index, action)), &state, enhancements));
 
         (new_index, state)
     }
-    fn backpropagate(&mut self, node_index: Index, value: isize) {
-        if let Some(parent_index) =
+    // The value is given from the perspective of the player who moved into
+    // the leaf. The playout lists the actions played after the leaf.
+    fn backpropagate(&mut self, leaf_index: Index, mut value: isize, playout: Vec<Position2>) {
+        // All actions played after the current node, including the playout.
+        let mut following = playout;
+        let mut node_index = leaf_index;
+
+        loop {
             {
-                // This is wrapped in { .. } in order to drop the mutable reference
-                // to the node before using &mut self again.
-                let ref mut node = self.storage[node_index.0];
+                let node = &mut self.storage[node_index.0];
                 node.win_count += value;
                 node.simulation_count += 1;
-                node.parent
+
+                // The player to move in this node plays every other action.
+                // Only the first time an action is played counts.
+                let mut seen = [false; 16];
+                for action in following.iter().step_by(2) {
+                    let i = action.0 as usize;
+                    if !seen[i] {
+                        seen[i] = true;
+                        node.amaf_win_count[i] -= value;
+                        node.amaf_simulation_count[i] += 1;
+                    }
+                }
             }
-        {
-            self.backpropagate(parent_index, -value);
-        }
 
+            self.update_proof(node_index);
+
+            let node = &self.storage[node_index.0];
+            match (node.parent, node.action) {
+                (Some(parent_index), Some(action)) => {
+                    following.insert(0, action);
+                    node_index = parent_index;
+                    value = -value;
+                }
+                _ => break,
+            }
+        }
+    }
+    // Derives the proof of a node from the proofs of its children.
+    fn update_proof(&mut self, node_index: Index) {
+        let proof = {
+            let node = &self.storage[node_index.0];
+            if node.proof != Proof::Unknown {
+                return;
+            }
+            // A full board without a winner is a draw, which is never
+            // proven. So at least one legal child has to be lost.
+            let mut all_lost = true;
+            let mut any_legal = false;
+            let mut any_won = false;
+            for i in 0..16 {
+                any_legal |= node.children[i] != ChildRef::IllegalMove;
+                match self.child_proof(node, i) {
+                    Some(Proof::Win) => any_won = true,
+                    Some(Proof::Loss) => {}
+                    Some(Proof::Unknown) => all_lost = false,
+                    None => all_lost &= node.children[i] == ChildRef::IllegalMove,
+                }
+            }
+            if any_won {
+                Proof::Loss
+            } else if all_lost && any_legal {
+                Proof::Win
+            } else {
+                Proof::Unknown
+            }
+        };
+        self.storage[node_index.0].proof = proof;
     }
 }
 
-
 impl MCTreeAI {
-    pub fn new(endurance: usize, exploration: f32) -> Self {
+    pub fn new(
+        endurance: usize,
+        exploration: f32,
+        tactics: Tactics,
+        policy: Policy,
+        enhancements: Enhancements,
+    ) -> Self {
         MCTreeAI {
             endurance,
             exploration,
+            tactics,
+            policy,
+            enhancements,
         }
     }
     fn create_tree_async(&self, state: &game::State) -> VecTree {
@@ -242,7 +616,7 @@ impl MCTreeAI {
         let pool = ThreadPool::new(worker_count);
 
         // Set up an empty tree.
-        let mut tree = VecTree::new(self.endurance + 1, state);
+        let mut tree = VecTree::new(self.endurance + 1, state, self.enhancements);
         assert!(
             self.endurance > worker_count,
             "The endurance must be larger than {}.",
@@ -262,11 +636,11 @@ impl MCTreeAI {
             }
 
             // Now wait for results, create new work to replace it.
-            while let Ok((leaf_index, score)) = receiver.recv() {
+            while let Ok((leaf_index, score, playout)) = receiver.recv() {
                 // Backpropagation
-                tree.backpropagate(leaf_index, -score);
+                tree.backpropagate(leaf_index, -score, playout);
 
-                if endurance_left > 0 {
+                if endurance_left > 0 && !tree.solved() {
                     endurance_left -= 1;
                     self.create_simulation_task(&mut tree, state, &sender, &pool);
                 } else {
@@ -277,8 +651,8 @@ impl MCTreeAI {
         };
 
         // Wait for the very last results
-        while let Ok((leaf_index, score)) = receiver.recv() {
-            tree.backpropagate(leaf_index, -score);
+        while let Ok((leaf_index, score, playout)) = receiver.recv() {
+            tree.backpropagate(leaf_index, -score, playout);
         }
 
         tree
@@ -287,27 +661,162 @@ impl MCTreeAI {
         &self,
         tree: &mut VecTree,
         state: &game::State,
-        sender: &Sender<(Index, isize)>,
+        sender: &Sender<(Index, isize, Vec<Position2>)>,
         pool: &ThreadPool,
     ) {
-        use ai::mc::random_playout;
+        use ai::mc::recorded_playout;
         // Selection & Expansion
-        let (leaf_index, leaf_state) = tree.select_best(Index(0), state.clone(), self.exploration);
+        let (leaf_index, leaf_state) =
+            tree.select_best(Index(0), state.clone(), self.exploration, self.enhancements);
+
+        // Proven nodes don't need a simulation, the result is known.
+        let proof = tree.storage[leaf_index.0].proof;
+        if proof != Proof::Unknown {
+            let score = if proof == Proof::Win { -1 } else { 1 };
+            sender.send((leaf_index, score, vec![])).unwrap();
+            return;
+        }
 
         // Simulation
         let sender_clone = sender.clone();
+        let policy = self.policy;
+        let heavy = self.tactics.in_playouts();
         pool.execute(move || {
             let current_color = leaf_state.current_color;
-            let score = random_playout(leaf_state).scoring(current_color).unwrap() as isize;
+            let (result, playout) = recorded_playout(leaf_state, policy, heavy);
+            let score = result.scoring(current_color).unwrap() as isize;
 
-            sender_clone.send((leaf_index, score)).unwrap();
+            sender_clone.send((leaf_index, score, playout)).unwrap();
         });
     }
 }
 
 
+impl MCTreeAI {
+    // Runs the same search as action, but returns the statistics as well.
+    pub fn analyse(&self, state: &game::State) -> Analysis {
+        let tree = self.create_tree_async(state);
+        let forced = if self.tactics.at_root() {
+            forced_action(state)
+        } else {
+            None
+        };
+        tree.analysis(forced.unwrap_or_else(|| tree.robust_move()))
+    }
+}
+
 impl StatelessAI for MCTreeAI {
     fn action(&self, state: &game::State) -> Position2 {
+        if self.tactics.at_root() {
+            if let Some(action) = forced_action(state) {
+                return action;
+            }
+        }
         self.create_tree_async(state).robust_move()
     }
 }
+
+#[test]
+fn test_amaf_statistics() {
+    use constants::LINES;
+    use std::sync::Arc;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let state = game::State::new(structure);
+    let enhancements = Enhancements {
+        rave: Some(300.0),
+        ..Enhancements::default()
+    };
+    let mut tree = VecTree::new(10, &state, enhancements);
+    let (child, _) = tree.initialize_child(Index(0), 0, state, enhancements);
+
+    // White played A1 into the child, then the playout continues with
+    // Black B1, White C1 (twice, only the first counts) and Black D1.
+    let playout = vec![Position2(1), Position2(2), Position2(3), Position2(2)];
+    tree.backpropagate(child, 1, playout);
+
+    // White moves at the root and won.
+    let root = &tree.storage[0];
+    assert_eq!(root.amaf_simulation_count[0], 1);
+    assert_eq!(root.amaf_simulation_count[2], 1);
+    assert_eq!(root.amaf_win_count[0], 1);
+    assert_eq!(root.amaf_win_count[2], 1);
+    assert_eq!(root.amaf_simulation_count[1], 0);
+    assert_eq!(root.amaf_simulation_count[3], 0);
+
+    // Black moves in the child and lost.
+    let child = &tree.storage[child.0];
+    assert_eq!(child.amaf_simulation_count[1], 1);
+    assert_eq!(child.amaf_simulation_count[3], 1);
+    assert_eq!(child.amaf_win_count[1], -1);
+    assert_eq!(child.amaf_win_count[3], -1);
+    assert_eq!(child.amaf_simulation_count[2], 0);
+}
+
+#[test]
+fn test_priors_and_widening() {
+    use constants::LINES;
+    use std::sync::Arc;
+
+    // White can complete the first row at D1 and has to block A2.
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let position = "w/w/w/-/-/b/b/b/-/-/-/-/-/-/-/- w";
+    let state = game::State::from_position_string(structure, position).unwrap();
+    let enhancements = Enhancements {
+        widening: Some(1.0),
+        ..Enhancements::default()
+    };
+    let mut tree = VecTree::new(10, &state, enhancements);
+
+    {
+        let root = &tree.storage[0];
+        assert_eq!(root.order.len(), 16);
+        assert_eq!(root.order[0], 3);
+        assert_eq!(root.priors[3], 1.0);
+        assert_eq!(root.order[1], 4);
+        assert_eq!(root.priors[4], 0.8);
+        for pair in root.order.windows(2) {
+            assert!(root.priors[pair[0]] >= root.priors[pair[1]]);
+        }
+    }
+
+    // ceil(sqrt(visits + 1)) children are candidates, best first.
+    for &(visits, allowed) in &[(0, 1), (1, 2), (3, 2), (4, 3), (15, 4), (255, 16)] {
+        tree.storage[0].simulation_count = visits;
+        let candidates = tree.candidates(Index(0), enhancements);
+        assert_eq!(candidates.len(), allowed);
+        assert_eq!(&candidates[..], &tree.storage[0].order[..allowed]);
+    }
+
+    // Without widening, all legal children are candidates.
+    assert_eq!(tree.candidates(Index(0), Enhancements::default()).len(), 16);
+}
+
+#[test]
+fn test_robust_move_avoids_losses() {
+    use constants::LINES;
+    use std::sync::Arc;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let state = game::State::new(structure);
+    let enhancements = Enhancements::default();
+    let mut tree = VecTree::new(20, &state, enhancements);
+
+    // Only A1 and B1 were tried and both are proven losses, as may happen
+    // with progressive widening. The untried actions are preferred.
+    for i in 0..2 {
+        let (child, _) = tree.initialize_child(Index(0), i, state.clone(), enhancements);
+        tree.storage[child.0].simulation_count = 5 + i;
+        tree.storage[child.0].proof = Proof::Loss;
+    }
+    let action = tree.robust_move();
+    assert!(action.0 >= 2);
+
+    // Once every action is a proven loss, the most simulated one is played.
+    for i in 2..16 {
+        let (child, _) = tree.initialize_child(Index(0), i, state.clone(), enhancements);
+        tree.storage[child.0].simulation_count = 1;
+        tree.storage[child.0].proof = Proof::Loss;
+    }
+    assert_eq!(tree.robust_move(), Position2(1));
+}
@@ -5,7 +5,10 @@ pub mod random;
 pub mod mc;
 pub mod tree;
 pub mod value;
-mod mctree;
+pub mod book;
+pub mod solver;
+pub mod tactics;
+pub mod mctree;
 
 use game;
 use game::Position2;
@@ -17,15 +20,34 @@ pub trait StatelessAI {
     fn action(&self, state: &game::State) -> Position2;
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Constructor {
-    Random,
-    MonteCarlo { endurance: usize },
+    Random { tactics: tactics::Tactics },
+    MonteCarlo {
+        endurance: usize,
+        tactics: tactics::Tactics,
+        policy: mc::Policy,
+    },
     Tree {
         depth: u8,
         value_function: value::Simple,
     },
-    MonteCarloTree { endurance: usize, exploration: f32 },
+    MonteCarloTree {
+        endurance: usize,
+        exploration: f32,
+        tactics: tactics::Tactics,
+        policy: mc::Policy,
+        enhancements: mctree::Enhancements,
+    },
+    Book {
+        book: Arc<book::OpeningBook>,
+        fallback: Box<Constructor>,
+    },
+    Solver {
+        min_pieces: u8,
+        budget: usize,
+        fallback: Box<Constructor>,
+    },
 }
 
 pub enum AIBox {
@@ -33,13 +55,19 @@ pub enum AIBox {
     MC(mc::MonteCarloAI),
     Tree(tree::TreeJudgementAI),
     MCTree(mctree::MCTreeAI),
+    Book(book::BookAI),
+    Solver(solver::SolverAI),
 }
 
 impl AIBox {
     pub fn new(ai_parameter: Constructor) -> AIBox {
         match ai_parameter {
-            Constructor::Random => AIBox::Random(random::RandomSogoAI::new()),
-            Constructor::MonteCarlo { endurance } => AIBox::MC(mc::MonteCarloAI::new(endurance)),
+            Constructor::Random { tactics } => AIBox::Random(random::RandomSogoAI::new(tactics)),
+            Constructor::MonteCarlo {
+                endurance,
+                tactics,
+                policy,
+            } => AIBox::MC(mc::MonteCarloAI::new(endurance, tactics, policy)),
             Constructor::Tree {
                 depth,
                 value_function,
@@ -47,7 +75,38 @@ impl AIBox {
             Constructor::MonteCarloTree {
                 endurance,
                 exploration,
-            } => AIBox::MCTree(mctree::MCTreeAI::new(endurance, exploration)),
+                tactics,
+                policy,
+                enhancements,
+            } => AIBox::MCTree(mctree::MCTreeAI::new(
+                endurance,
+                exploration,
+                tactics,
+                policy,
+                enhancements,
+            )),
+            Constructor::Book { book, fallback } => {
+                AIBox::Book(book::BookAI::new(book, AIBox::new(*fallback)))
+            }
+            Constructor::Solver {
+                min_pieces,
+                budget,
+                fallback,
+            } => AIBox::Solver(solver::SolverAI::new(
+                min_pieces,
+                budget,
+                AIBox::new(*fallback),
+            )),
+        }
+    }
+}
+
+impl AIBox {
+    // Only the tree search AIs can explain their decision.
+    pub fn analyse(&self, state: &game::State) -> Option<mctree::Analysis> {
+        match *self {
+            AIBox::MCTree(ref ai) => Some(ai.analyse(state)),
+            _ => None,
         }
     }
 }
@@ -59,6 +118,8 @@ impl StatelessAI for AIBox {
             &AIBox::MC(ref ai) => ai.action(state),
             &AIBox::Tree(ref ai) => ai.action(state),
             &AIBox::MCTree(ref ai) => ai.action(state),
+            &AIBox::Book(ref ai) => ai.action(state),
+            &AIBox::Solver(ref ai) => ai.action(state),
         }
     }
 }
@@ -82,29 +143,34 @@ impl StatelessAI for AIBox {
 }*/
 
 
+// Used by the tests, everything else starts from a given position.
+#[allow(dead_code)]
 pub fn run_match<T: StatelessAI, U: StatelessAI>(
     structure: Arc<game::Structure>,
     white_player: &mut T,
     black_player: &mut U,
 ) -> game::State {
-    let mut i = 0;
+    run_match_from(game::State::new(structure), white_player, black_player)
+}
 
-    let mut state = game::State::new(structure.clone());
+// Like run_match, but the game continues from the given position.
+pub fn run_match_from<T: StatelessAI, U: StatelessAI>(
+    mut state: game::State,
+    white_player: &mut T,
+    black_player: &mut U,
+) -> game::State {
     while state.victory_state == game::VictoryState::Undecided {
         if state.age == 64 {
             state.victory_state = game::VictoryState::Draw;
             return state;
         }
-        let action = if i % 2 == 0 {
-            white_player.action(&state)
-        } else {
-            black_player.action(&state)
+        let action = match state.current_color {
+            game::Color::White => white_player.action(&state),
+            game::Color::Black => black_player.action(&state),
         };
         state.execute(action);
-        i += 1;
     }
-    // println!("{:?}", i);
-    return state;
+    state
 }
 
 
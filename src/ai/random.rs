@@ -4,18 +4,26 @@ use game::Position2;
 
 use rand::{thread_rng, Rng};
 use ai::StatelessAI;
+use ai::tactics::{forced_action, Tactics};
 
 // An AI which executes random legal actions
-pub struct RandomSogoAI {}
+pub struct RandomSogoAI {
+    tactics: Tactics,
+}
 
 impl RandomSogoAI {
-    pub fn new() -> RandomSogoAI {
-        RandomSogoAI {}
+    pub fn new(tactics: Tactics) -> RandomSogoAI {
+        RandomSogoAI { tactics }
     }
 }
 
 impl StatelessAI for RandomSogoAI {
     fn action(&self, state: &game::State) -> Position2 {
+        if self.tactics.at_root() {
+            if let Some(action) = forced_action(state) {
+                return action;
+            }
+        }
         let legal_actions: Vec<Position2> = state.legal_actions().collect();
         *thread_rng().choose(&legal_actions).unwrap()
     }
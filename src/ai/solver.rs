@@ -0,0 +1,267 @@
+/* Perfect play for late positions.
+
+The solver runs an alpha-beta search over bitboards. Results are stored in a
+transposition table keyed by the pieces of both players. As the number of
+pieces determines how many actions were played, the distance to the end of the
+game can be stored in the table as well, so all results are exact.
+
+The search is aborted once it visited more nodes than its budget allows. */
+
+use std::collections::HashMap;
+
+use ai::{AIBox, StatelessAI};
+use game;
+use game::{Color, PointState, Position2, Structure};
+
+// The value of a win on the very next action. Later wins are worth less, so
+// the solver prefers the fastest win and the slowest loss.
+const WIN: i32 = 100;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    // The side to move wins. The number counts the actions until the winning
+    // piece is placed, including the actions of both players.
+    Win(u8),
+    // The side to move loses after this many actions.
+    Loss(u8),
+    Draw,
+}
+
+impl Outcome {
+    fn from_score(score: i32) -> Self {
+        if score > 0 {
+            Outcome::Win((WIN + 1 - score) as u8)
+        } else if score < 0 {
+            Outcome::Loss((WIN + 1 + score) as u8)
+        } else {
+            Outcome::Draw
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Copy, Clone)]
+struct Board {
+    // Pieces of the player who moves next.
+    mine: u64,
+    theirs: u64,
+    column_height: [u8; 16],
+    age: u8,
+}
+
+impl Board {
+    fn new(state: &game::State) -> Self {
+        let mut mine = 0;
+        let mut theirs = 0;
+        for (index, point) in state.points.iter().enumerate() {
+            if let PointState::Piece(color) = *point {
+                if color == state.current_color {
+                    mine |= 1 << index;
+                } else {
+                    theirs |= 1 << index;
+                }
+            }
+        }
+        Board {
+            mine,
+            theirs,
+            column_height: state.column_height,
+            age: state.age,
+        }
+    }
+    // Where a piece placed into the column would land.
+    fn landing(&self, column: usize) -> Option<usize> {
+        let height = self.column_height[column] as usize;
+        if height < 4 { Some(column + 16 * height) } else { None }
+    }
+    // Would the side to move complete a subset by placing at this position?
+    fn wins_at(&self, structure: &Structure, position: usize) -> bool {
+        let pieces = self.mine | 1 << position;
+        structure.reverse[position].iter().any(|&index| {
+            let mask = structure.source[index].0;
+            pieces & mask == mask
+        })
+    }
+    fn winning_column(&self, structure: &Structure) -> Option<usize> {
+        (0..16).find(|&column| match self.landing(column) {
+            Some(position) => self.wins_at(structure, position),
+            None => false,
+        })
+    }
+    // Places a piece for the side to move and hands the turn over.
+    fn play(&self, column: usize, position: usize) -> Board {
+        let mut column_height = self.column_height;
+        column_height[column] += 1;
+        Board {
+            mine: self.theirs,
+            theirs: self.mine | 1 << position,
+            column_height,
+            age: self.age + 1,
+        }
+    }
+}
+
+pub struct Solver<'a> {
+    structure: &'a Structure,
+    table: HashMap<(u64, u64), (Bound, i32)>,
+    // Columns sorted by how many subsets pass through them, good columns first.
+    column_order: Vec<usize>,
+    nodes: usize,
+    budget: usize,
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(structure: &'a Structure, budget: usize) -> Self {
+        let mut column_order: Vec<usize> = (0..16).collect();
+        column_order.sort_by_key(|&column| {
+            let subsets: usize = (0..4)
+                .map(|z| structure.reverse[column + 16 * z].len())
+                .sum();
+            -(subsets as isize)
+        });
+
+        Solver {
+            structure,
+            table: HashMap::new(),
+            column_order,
+            nodes: 0,
+            budget,
+        }
+    }
+    // How many positions were visited so far.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+    // Returns the exact outcome and the best action, or None if the budget
+    // ran out. The action is None when the game is already over.
+    pub fn solve(&mut self, state: &game::State) -> Option<(Outcome, Option<Position2>)> {
+        match state.victory_state {
+            game::VictoryState::Win { .. } => return Some((Outcome::Loss(0), None)),
+            game::VictoryState::Draw => return Some((Outcome::Draw, None)),
+            game::VictoryState::Undecided => {}
+        }
+
+        let board = Board::new(state);
+        if let Some(column) = board.winning_column(self.structure) {
+            return Some((Outcome::Win(1), Some(Position2(column as u8))));
+        }
+
+        let mut alpha = -WIN - 1;
+        let mut best_action = None;
+
+        for i in 0..16 {
+            let column = self.column_order[i];
+            if let Some(position) = board.landing(column) {
+                let score = -self.negamax(&board.play(column, position), -WIN - 1, -alpha, 1)?;
+                if score > alpha {
+                    alpha = score;
+                    best_action = Some(Position2(column as u8));
+                }
+            }
+        }
+
+        Some((Outcome::from_score(alpha), best_action))
+    }
+    // Scores are given from the perspective of the side to move. `ply` counts
+    // the actions played since the root of the search.
+    fn negamax(&mut self, board: &Board, mut alpha: i32, mut beta: i32, ply: i32) -> Option<i32> {
+        self.nodes += 1;
+        if self.nodes > self.budget {
+            return None;
+        }
+        if board.age == 64 {
+            return Some(0);
+        }
+
+        // Winning right away can't be beaten, so check this first.
+        if board.winning_column(self.structure).is_some() {
+            return Some(WIN - ply);
+        }
+
+        let key = (board.mine, board.theirs);
+        let original_alpha = alpha;
+        if let Some(&(bound, value)) = self.table.get(&key) {
+            match bound {
+                Bound::Exact => return Some(value),
+                Bound::Lower => alpha = alpha.max(value),
+                Bound::Upper => beta = beta.min(value),
+            }
+            if alpha >= beta {
+                return Some(value);
+            }
+        }
+
+        let mut best = -WIN - 1;
+        for i in 0..16 {
+            let column = self.column_order[i];
+            if let Some(position) = board.landing(column) {
+                let score = -self.negamax(&board.play(column, position), -beta, -alpha, ply + 1)?;
+                if score > best {
+                    best = score;
+                }
+                if best > alpha {
+                    alpha = best;
+                }
+                if alpha >= beta {
+                    break;
+                }
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(key, (bound, best));
+
+        Some(best)
+    }
+}
+
+// Describes the outcome from the perspective of the side to move.
+pub fn describe(outcome: Outcome, color: Color) -> String {
+    match outcome {
+        Outcome::Win(distance) => format!("{:?} wins after {} actions.", color, distance),
+        Outcome::Loss(distance) => format!("{:?} wins after {} actions.", !color, distance),
+        Outcome::Draw => "The game ends in a draw.".to_owned(),
+    }
+}
+
+// Plays perfectly once enough pieces are on the board and the solver finishes
+// within its budget. Otherwise the fallback AI decides.
+pub struct SolverAI {
+    min_pieces: u8,
+    budget: usize,
+    fallback: Box<AIBox>,
+}
+
+impl SolverAI {
+    pub fn new(min_pieces: u8, budget: usize, fallback: AIBox) -> Self {
+        SolverAI {
+            min_pieces,
+            budget,
+            fallback: Box::new(fallback),
+        }
+    }
+}
+
+impl StatelessAI for SolverAI {
+    fn action(&self, state: &game::State) -> Position2 {
+        if state.age >= self.min_pieces {
+            let mut solver = Solver::new(&state.structure, self.budget);
+            if let Some((_, Some(action))) = solver.solve(state) {
+                return action;
+            }
+        }
+        self.fallback.action(state)
+    }
+}
@@ -0,0 +1,82 @@
+/* Tactical shortcuts shared by the sampling based AIs.
+
+Random playouts don't notice that a single action wins the game or that the
+opponent is about to complete a subset. Before sampling, an AI can check
+whether the action is forced anyway: Winning comes first, blocking the
+opponent second. The same check can be used in every step of a playout, which
+makes the playouts slower but much more realistic ("heavy playouts"). The
+playout policies of ai::mc::Policy only decide the actions which aren't
+forced. */
+
+use std::str::FromStr;
+
+use game;
+use game::{Color, PointState, Position2, Position3};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tactics {
+    // Rely on sampling alone.
+    Off,
+    // Only check for forced actions before sampling.
+    Root,
+    // Also check for forced actions in every step of every playout, whatever
+    // the playout policy.
+    Heavy,
+}
+
+impl Tactics {
+    pub fn at_root(self) -> bool {
+        self != Tactics::Off
+    }
+    pub fn in_playouts(self) -> bool {
+        self == Tactics::Heavy
+    }
+}
+
+impl FromStr for Tactics {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Tactics, ()> {
+        match s {
+            "off" => Ok(Tactics::Off),
+            "root" => Ok(Tactics::Root),
+            "heavy" => Ok(Tactics::Heavy),
+            _ => Err(()),
+        }
+    }
+}
+
+// Would a piece of this color complete a subset at the given empty position?
+fn completes_subset(state: &game::State, position: Position3, color: Color) -> bool {
+    state.structure.reverse[position.0 as usize]
+        .iter()
+        .any(|&subset_index| {
+            state.structure.source[subset_index].iter().all(|other| {
+                other == position || state.at(other) == PointState::Piece(color)
+            })
+        })
+}
+
+// All columns where a piece of this color would complete a subset right now.
+pub fn winning_actions(state: &game::State, color: Color) -> Vec<Position2> {
+    state
+        .legal_actions()
+        .filter(|&column| {
+            let height = state.column_height[column.0 as usize];
+            completes_subset(state, column.with_height(height), color)
+        })
+        .collect()
+}
+
+// Returns an action which wins the game for the side to move, or otherwise
+// an action which keeps the opponent from winning with their next action.
+// If the opponent has several winning actions, only one of them is blocked.
+pub fn forced_action(state: &game::State) -> Option<Position2> {
+    if !state.victory_state.active() {
+        return None;
+    }
+    let color = state.current_color;
+    winning_actions(state, color)
+        .into_iter()
+        .chain(winning_actions(state, !color))
+        .next()
+}
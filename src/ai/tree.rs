@@ -12,23 +12,46 @@ pub fn recursive_judgement(
     my_color: game::Color,
     depth: u8,
     value_function: value::Simple,
+) -> i32 {
+    alpha_beta(state, my_color, depth, value_function, i32::MIN, i32::MAX)
+}
+
+// Minimax with alpha-beta pruning. The result is exact as long as it lies
+// strictly between alpha and beta. Trying promising actions first (see
+// value::ordered_actions) makes the pruning much more effective.
+fn alpha_beta(
+    state: &game::State,
+    my_color: game::Color,
+    depth: u8,
+    value_function: value::Simple,
+    mut alpha: i32,
+    mut beta: i32,
 ) -> i32 {
     if depth == 0 || !state.victory_state.active() {
-        value_function.value_of(state, my_color)
-    } else {
-        let values = state.legal_actions().map(|action| {
-            let mut new_state = state.clone();
-            new_state.execute(action);
-            let value = recursive_judgement(&new_state, my_color, depth - 1, value_function);
-            value
-        });
+        return value_function.value_of(state, my_color);
+    }
+
+    let maximizing = state.current_color == my_color;
+    let mut best = if maximizing { i32::MIN } else { i32::MAX };
 
-        if state.current_color == my_color {
-            values.max()
+    for action in value::ordered_actions(state) {
+        let mut new_state = state.clone();
+        new_state.execute(action);
+        let value = alpha_beta(&new_state, my_color, depth - 1, value_function, alpha, beta);
+
+        if maximizing {
+            best = best.max(value);
+            alpha = alpha.max(best);
         } else {
-            values.min()
-        }.unwrap()
+            best = best.min(value);
+            beta = beta.min(best);
+        }
+        if alpha >= beta {
+            break;
+        }
     }
+
+    best
 }
 
 pub struct TreeJudgementAI {
@@ -1,5 +1,9 @@
 /* This module collects value functions. */
 
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
 use std::str::FromStr;
 
 use game;
@@ -9,6 +13,9 @@ use game::{LineState, Position3, Position2};
 pub enum Simple {
     Subsets,
     WinOnly,
+    Threats,
+    Parity,
+    Weighted(Weights),
 }
 
 impl Simple {
@@ -16,6 +23,9 @@ impl Simple {
         match self {
             Simple::Subsets => subsets(state, my_color),
             Simple::WinOnly => win_only(state, my_color),
+            Simple::Threats => threats(state, my_color),
+            Simple::Parity => parity(state, my_color),
+            Simple::Weighted(ref weights) => weighted(state, my_color, weights),
         }
     }
 }
@@ -26,6 +36,9 @@ impl FromStr for Simple {
         match s {
             "subsets" => Ok(Simple::Subsets),
             "win" => Ok(Simple::WinOnly),
+            "threats" => Ok(Simple::Threats),
+            "parity" => Ok(Simple::Parity),
+            "weighted" => Ok(Simple::Weighted(Weights::default())),
             _ => Err(()),
         }
     }
@@ -107,7 +120,7 @@ be used to pick which actions to inspect further. */
 // variant indicates that playing this action wins the game.
 // The DirectLoss variant indicates that the player is sure to loose by playing this.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum SideValue {
+pub enum SideValue {
     // Do I win if I place here?
     LastMissingPiece,
     // Or do I at least get a piece which aligns with a lot of other pieces?
@@ -116,7 +129,6 @@ enum SideValue {
 }
 
 // Calculates the point value for White and Black.
-#[allow(dead_code)]
 fn point_value(state: &game::State, position: Position3) -> Option<(SideValue, SideValue)> {
     use game::Color::White;
 
@@ -178,8 +190,7 @@ fn point_value(state: &game::State, position: Position3) -> Option<(SideValue, S
 }
 
 // Is this column worth playing at?
-#[allow(dead_code)]
-fn column_value(state: &game::State, position: Position2) -> Option<(SideValue, SideValue)> {
+pub fn column_value(state: &game::State, position: Position2) -> Option<(SideValue, SideValue)> {
     use self::SideValue::{LastMissingPiece, Heuristic, DirectLoss};
     let height: u8 = state.column_height[position.0 as usize];
 
@@ -220,3 +231,488 @@ fn column_value(state: &game::State, position: Position2) -> Option<(SideValue,
         }
     }
 }
+
+// The threats of one side. A threat is an empty position which completes a
+// subset for this side.
+#[derive(Clone, Debug, Default)]
+pub struct SideThreats {
+    // All threats, no matter how far up in their column they are.
+    pub threats: Vec<Position3>,
+    // Columns where this side wins by placing a piece right now.
+    pub immediate: Vec<Position2>,
+    // Columns where a piece of this side would land directly below an
+    // opponent threat. Playing there lets the opponent win.
+    pub poisoned: Vec<Position2>,
+}
+
+impl SideThreats {
+    // A double threat can't be parried: Either there are two winning columns,
+    // or there is a second threat directly above a winning column.
+    pub fn double_threat(&self) -> bool {
+        self.immediate.len() >= 2 ||
+            self.immediate.iter().any(|column| {
+                // Threats are listed from bottom to top, so the first threat
+                // in an immediate column is where the next piece lands.
+                let mut in_column = self.threats.iter().filter(|position| {
+                    Position2::from(**position) == *column
+                });
+                match (in_column.next(), in_column.next()) {
+                    (Some(lower), Some(upper)) => upper.0 == lower.0 + 16,
+                    _ => false,
+                }
+            })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ThreatAnalysis {
+    pub white: SideThreats,
+    pub black: SideThreats,
+}
+
+impl ThreatAnalysis {
+    pub fn side(&self, color: game::Color) -> &SideThreats {
+        match color {
+            game::Color::White => &self.white,
+            game::Color::Black => &self.black,
+        }
+    }
+}
+
+// Lists the threats of both players. Only meaningful while the game is active.
+pub fn analyse_threats(state: &game::State) -> ThreatAnalysis {
+    use self::SideValue::{LastMissingPiece, DirectLoss};
+
+    let mut white = SideThreats::default();
+    let mut black = SideThreats::default();
+
+    for column in 0..16 {
+        let column = Position2(column);
+        let height = state.column_height[column.0 as usize];
+
+        for z in height..4 {
+            let position = column.with_height(z);
+            let (white_value, black_value) = point_value(state, position).unwrap();
+            if white_value == LastMissingPiece {
+                white.threats.push(position);
+            }
+            if black_value == LastMissingPiece {
+                black.threats.push(position);
+            }
+        }
+
+        if let Some((white_value, black_value)) = column_value(state, column) {
+            match white_value {
+                LastMissingPiece => white.immediate.push(column),
+                DirectLoss => white.poisoned.push(column),
+                _ => {}
+            }
+            match black_value {
+                LastMissingPiece => black.immediate.push(column),
+                DirectLoss => black.poisoned.push(column),
+                _ => {}
+            }
+        }
+    }
+
+    ThreatAnalysis { white, black }
+}
+
+// Extends the subsets value function by the threats both sides have. Threats
+// which decide the game within the next two actions are valued almost as high
+// as a win, all other threats give a bonus.
+pub fn threats(state: &game::State, my_color: game::Color) -> i32 {
+    if !state.victory_state.active() {
+        return subsets(state, my_color);
+    }
+
+    let analysis = analyse_threats(state);
+    let my_threats = analysis.side(my_color);
+    let their_threats = analysis.side(!my_color);
+    let my_turn = state.current_color == my_color;
+
+    if my_turn && !my_threats.immediate.is_empty() {
+        return 900;
+    }
+    if !my_turn && !their_threats.immediate.is_empty() {
+        return -900;
+    }
+    if !my_turn && my_threats.double_threat() {
+        return 800;
+    }
+    if my_turn && their_threats.double_threat() {
+        return -800;
+    }
+
+    let threat_balance = my_threats.threats.len() as i32 - their_threats.threats.len() as i32;
+    subsets(state, my_color) + 10 * threat_balance
+}
+
+/* Once the board fills up, nobody wants to place a piece directly below an
+opponent threat. The players then have to fill the remaining columns and the
+parity of the height decides who is forced to give way. As there is an even
+number of positions in each column, White gets the odd layers (z = 0 and 2)
+and Black the even layers (z = 1 and 3) if both just follow up on each other.
+A threat on a layer of its own parity is therefore good, the others are only
+useful if the opponent has nothing better. */
+
+// Is this a layer where `color` gets to place pieces in the zugzwang race?
+pub fn favourable_layer(color: game::Color, position: Position3) -> bool {
+    let (_, _, z) = position.coords();
+    match color {
+        game::Color::White => z % 2 == 0,
+        game::Color::Black => z % 2 == 1,
+    }
+}
+
+// The threats of one side which can still be reached: There is no opponent
+// threat below them in the same column.
+#[derive(Clone, Debug, Default)]
+pub struct ParityThreats {
+    // Threats on a favourable layer, see favourable_layer.
+    pub good: Vec<Position3>,
+    pub bad: Vec<Position3>,
+}
+
+impl ParityThreats {
+    fn push(&mut self, color: game::Color, position: Position3) {
+        if favourable_layer(color, position) {
+            self.good.push(position);
+        } else {
+            self.bad.push(position);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ParityAnalysis {
+    pub white: ParityThreats,
+    pub black: ParityThreats,
+}
+
+impl ParityAnalysis {
+    pub fn side(&self, color: game::Color) -> &ParityThreats {
+        match color {
+            game::Color::White => &self.white,
+            game::Color::Black => &self.black,
+        }
+    }
+    // Predicts who wins if the game comes down to zugzwang. This is the
+    // simple version of the rules known from Connect Four: a side with good
+    // threats wins if the opponent has none. Without any good threats, Black
+    // profits from odd threats as well, since White has to move first into
+    // every column that is left over.
+    pub fn zugzwang_winner(&self) -> Option<game::Color> {
+        use game::Color::{White, Black};
+
+        match (self.white.good.is_empty(), self.black.good.is_empty()) {
+            (false, true) => Some(White),
+            (true, false) => Some(Black),
+            (true, true) if !self.black.bad.is_empty() && self.white.bad.is_empty() => {
+                Some(Black)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Classifies the threats of both players by their height.
+pub fn analyse_parity(state: &game::State) -> ParityAnalysis {
+    use self::SideValue::LastMissingPiece;
+
+    let mut white = ParityThreats::default();
+    let mut black = ParityThreats::default();
+
+    for column in 0..16 {
+        let column = Position2(column);
+        let height = state.column_height[column.0 as usize];
+        // Once an opponent threat is found, everything above is out of reach.
+        let mut white_reachable = true;
+        let mut black_reachable = true;
+
+        for z in height..4 {
+            let position = column.with_height(z);
+            let (white_value, black_value) = point_value(state, position).unwrap();
+            let white_threat = white_value == LastMissingPiece;
+            let black_threat = black_value == LastMissingPiece;
+
+            if white_threat && white_reachable {
+                white.push(game::Color::White, position);
+            }
+            if black_threat && black_reachable {
+                black.push(game::Color::Black, position);
+            }
+
+            white_reachable &= !black_threat;
+            black_reachable &= !white_threat;
+        }
+    }
+
+    ParityAnalysis { white, black }
+}
+
+// Extends the threats value function by the parity of the threats. The
+// predicted winner of the zugzwang race gets a large bonus.
+pub fn parity(state: &game::State, my_color: game::Color) -> i32 {
+    let base = threats(state, my_color);
+    if !state.victory_state.active() || base.abs() >= 800 {
+        return base;
+    }
+
+    let analysis = analyse_parity(state);
+    let good_balance = analysis.side(my_color).good.len() as i32 -
+        analysis.side(!my_color).good.len() as i32;
+    let prediction = match analysis.zugzwang_winner() {
+        Some(color) if color == my_color => 100,
+        Some(_) => -100,
+        None => 0,
+    };
+
+    base + 20 * good_balance + prediction
+}
+
+#[test]
+fn test_parity_analysis() {
+    use game::{Structure, State};
+    use std::sync::Arc;
+    use constants::LINES;
+
+    let structure = Arc::new(Structure::new(&LINES));
+    // White threatens D1 on the third layer, which is not playable yet.
+    let position = "wbw/bbw/bww/b/-/-/-/-/-/-/-/-/-/-/-/- w";
+    let state = State::from_position_string(structure, position).unwrap();
+    let analysis = analyse_parity(&state);
+
+    assert_eq!(analysis.white.good, vec![Position3::new(3, 0, 2)]);
+    assert!(analysis.black.good.is_empty());
+    assert_eq!(analysis.zugzwang_winner(), Some(game::Color::White));
+    assert!(parity(&state, game::Color::White) > threats(&state, game::Color::White));
+}
+
+// Sorts the legal actions so that the most promising come first: Winning,
+// then blocking the opponent, then by heuristic value. Actions which let the
+// opponent win come last.
+pub fn ordered_actions(state: &game::State) -> Vec<Position2> {
+    use self::SideValue::{LastMissingPiece, Heuristic, DirectLoss};
+
+    let mut graded: Vec<(Position2, (u8, i32))> = state
+        .legal_actions()
+        .map(|column| {
+            let (white_value, black_value) = column_value(state, column).unwrap();
+            let (my_value, their_value) = match state.current_color {
+                game::Color::White => (white_value, black_value),
+                game::Color::Black => (black_value, white_value),
+            };
+            let grade = match (my_value, their_value) {
+                (LastMissingPiece, _) => (3, 0),
+                (_, LastMissingPiece) => (2, 0),
+                (Heuristic(value), _) => (1, value),
+                (DirectLoss, _) => (0, 0),
+            };
+            (column, grade)
+        })
+        .collect();
+
+    graded.sort_by_key(|&(_, grade)| Reverse(grade));
+    graded.into_iter().map(|(column, _)| column).collect()
+}
+
+#[test]
+fn test_threat_analysis() {
+    use game::{Structure, State};
+    use std::sync::Arc;
+    use constants::LINES;
+
+    let structure = Arc::new(Structure::new(&LINES));
+    // White threatens both D1 and A4, Black to move.
+    let position = "w/w/w/-/w/-/-/b/w/-/-/b/-/b/b/- b";
+    let state = State::from_position_string(structure, position).unwrap();
+    let analysis = analyse_threats(&state);
+
+    assert_eq!(analysis.white.immediate, vec![Position2::new(3, 0), Position2::new(0, 3)]);
+    assert!(analysis.white.double_threat());
+    assert!(analysis.black.immediate.is_empty());
+    assert_eq!(threats(&state, game::Color::White), 800);
+
+    // Blocking comes before everything else.
+    let ordered = ordered_actions(&state);
+    assert!(analysis.white.immediate.contains(&ordered[0]));
+}
+
+// Parameters of the weighted value function. They are stored in a text file
+// with one "name = value" pair per line, see Weights::NAMES for all names.
+// Parameters missing from the file keep their default value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weights {
+    // Score of a pure subset, indexed by the number of pieces in it.
+    pub count: [i32; 8],
+    // Score of a won game.
+    pub win: i32,
+    // Bonus for each empty position which completes a subset.
+    pub threat: i32,
+    // Bonus for a double threat, see SideThreats::double_threat.
+    pub double_threat: i32,
+    // Bonus for each threat on a favourable height. White profits from
+    // threats in the odd layers (1 and 3), Black from the even layers.
+    pub parity: i32,
+    // Bonus for each piece in the four central columns.
+    pub centre: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            count: [0, 1, 4, 9, 16, 25, 36, 49],
+            win: 1000,
+            threat: 10,
+            double_threat: 200,
+            parity: 0,
+            centre: 0,
+        }
+    }
+}
+
+impl Weights {
+    pub const NAMES: [&'static str; 12] = [
+        "count1",
+        "count2",
+        "count3",
+        "count4",
+        "count5",
+        "count6",
+        "count7",
+        "win",
+        "threat",
+        "double_threat",
+        "parity",
+        "centre",
+    ];
+
+    // All parameters are accessible by name, this makes reading, writing and
+    // tuning them simple.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut i32> {
+        match name {
+            "win" => Some(&mut self.win),
+            "threat" => Some(&mut self.threat),
+            "double_threat" => Some(&mut self.double_threat),
+            "parity" => Some(&mut self.parity),
+            "centre" => Some(&mut self.centre),
+            _ if name.starts_with("count") => {
+                let index = name[5..].parse::<usize>().ok()?;
+                self.count.get_mut(index)
+            }
+            _ => None,
+        }
+    }
+    pub fn get(&self, name: &str) -> Option<i32> {
+        // Weights are Copy, so the lookup of get_mut can be reused.
+        let mut weights = *self;
+        weights.get_mut(name).map(|value| *value)
+    }
+    pub fn parse(text: &str) -> Result<Weights, String> {
+        let mut weights = Weights::default();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap().trim();
+            let value = parts
+                .next()
+                .and_then(|value| value.trim().parse::<i32>().ok())
+                .ok_or_else(|| {
+                    format!("Line {} should read '<name> = <integer>'.", line_number + 1)
+                })?;
+            *weights.get_mut(name).ok_or_else(|| {
+                format!("Unknown parameter '{}' in line {}.", name, line_number + 1)
+            })? = value;
+        }
+
+        Ok(weights)
+    }
+    pub fn load(path: &str) -> Result<Weights, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|err| format!("Can't read the parameter file '{}': {}", path, err))?;
+        Weights::parse(&text)
+    }
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        File::create(path)
+            .and_then(|mut file| write!(file, "{}", self))
+            .map_err(|err| format!("Can't write the parameter file '{}': {}", path, err))
+    }
+}
+
+// Writes the weights in the format understood by Weights::parse.
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# Parameters for the weighted value function.")?;
+        for name in Weights::NAMES.iter() {
+            writeln!(f, "{} = {}", name, self.get(name).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+// Like the threats value function, but every term is scaled by a parameter.
+pub fn weighted(state: &game::State, my_color: game::Color, weights: &Weights) -> i32 {
+    if let game::VictoryState::Win { winner, .. } = state.victory_state {
+        return if winner == my_color { weights.win } else { -weights.win };
+    }
+
+    let mut score = 0;
+
+    for subset in &state.structure.source {
+        if let LineState::Pure { color, count } = subset.win_state(state) {
+            let value = weights.count.get(count as usize).cloned().unwrap_or(0);
+            score += if color == my_color { value } else { -value };
+        }
+    }
+
+    // The central columns are B2, C2, B3 and C3.
+    for &column in &[5, 6, 9, 10] {
+        let column = Position2(column);
+        for z in 0..state.column_height[column.0 as usize] {
+            if let game::PointState::Piece(color) = state.at(column.with_height(z)) {
+                score += if color == my_color { weights.centre } else { -weights.centre };
+            }
+        }
+    }
+
+    if state.victory_state.active() {
+        let analysis = analyse_threats(state);
+        for &color in &[game::Color::White, game::Color::Black] {
+            let side = analysis.side(color);
+            let sign = if color == my_color { 1 } else { -1 };
+
+            let favourable = side.threats
+                .iter()
+                .filter(|position| favourable_layer(color, **position))
+                .count() as i32;
+
+            score += sign * weights.threat * side.threats.len() as i32;
+            score += sign * weights.parity * favourable;
+            if side.double_threat() {
+                score += sign * weights.double_threat;
+            }
+        }
+    }
+
+    score
+}
+
+#[test]
+fn test_weights_file() {
+    let weights = Weights::parse("# Comment\ncount2 = 7\n  win=500\n").unwrap();
+    assert_eq!(weights.count[2], 7);
+    assert_eq!(weights.win, 500);
+    assert_eq!(weights.threat, Weights::default().threat);
+
+    assert_eq!(Weights::parse(&format!("{}", weights)), Ok(weights));
+
+    assert!(Weights::parse("unknown = 3").is_err());
+    assert!(Weights::parse("win = many").is_err());
+}
@@ -4,41 +4,113 @@ use clap;
 use clap::{App, Arg, SubCommand};
 
 use std::str::FromStr;
+use std::sync::Arc;
 
 use ai;
 use constants;
+use diagram;
+use game;
+use replay::History;
+use tune;
 
 pub enum Arguments {
+    // The evaluator is the AI which evaluates positions in the window when
+    // the evaluation is switched on.
     VsAI {
-        structure: constants::StructureSource,
+        start: game::State,
         opponent: ai::Constructor,
+        evaluator: ai::Constructor,
     },
     Demo {
-        structure: constants::StructureSource,
+        start: game::State,
         ai_1: ai::Constructor,
         ai_2: ai::Constructor,
+        evaluator: ai::Constructor,
     },
     Batch {
-        structure: constants::StructureSource,
+        start: game::State,
         ai_1: ai::Constructor,
         ai_2: ai::Constructor,
         count: usize,
     },
-    Humans { structure: constants::StructureSource, },
+    Humans {
+        start: game::State,
+        evaluator: ai::Constructor,
+    },
+    Book {
+        start: game::State,
+        ai: ai::Constructor,
+        // The AI as given on the command line, for the header of the book.
+        description: String,
+        depth: u8,
+        output: String,
+    },
+    Solve { start: game::State, budget: usize },
+    Tune {
+        start: game::State,
+        weights: ai::value::Weights,
+        settings: tune::Settings,
+    },
+    Diagram {
+        state: game::State,
+        highlight: Option<game::Subset>,
+        output: String,
+    },
+    Animate { history: History, output: String },
 }
 
 pub fn get_arguments() -> Result<Arguments, String> {
     let matches = setup_clap();
 
-    let structure = matches
+    let structure: game::Structure = matches
         .value_of("structure")
         .unwrap()
         .parse::<constants::StructureSource>()
-        .unwrap();
+        .unwrap()
+        .into();
+
+    let structure = Arc::new(structure);
+
+    // Diagrams may show finished games, so the position is set up directly.
+    if let Some(diagram_matches) = matches.subcommand_matches("diagram") {
+        let state = match matches.value_of("position") {
+            Some(position) => game::State::from_position_string(structure, position)?,
+            None => game::State::new(structure),
+        };
+        // Subsets are numbered from 1, as in the structure browser. Without
+        // a number, the winning subset is highlighted.
+        let highlight = match diagram_matches.value_of("highlight") {
+            Some(number) => {
+                let number = number.parse::<usize>().unwrap();
+                let count = state.structure.source.len();
+                if number == 0 || number > count {
+                    return Err(format!("There are only {} subsets, counted from 1.", count));
+                }
+                Some(state.structure.source[number - 1])
+            }
+            None => diagram::winning_subset(&state),
+        };
+        let output = diagram_matches.value_of("output").unwrap().to_owned();
+
+        return Ok(Arguments::Diagram {
+            state,
+            highlight,
+            output,
+        });
+    }
+
+    let start = starting_position(structure, matches.value_of("position"))?;
+
+    // The default parameters for the weighted value function.
+    let weights = match matches.value_of("eval-params") {
+        Some(path) => ai::value::Weights::load(path)?,
+        None => ai::value::Weights::default(),
+    };
+    let structure = start.structure.clone();
 
     if let Some(batch_matches) = matches.subcommand_matches("batch") {
-        let ai_1 = batch_matches.values_of("ai1").map(ai_parser).unwrap()?;
-        let ai_2 = batch_matches.values_of("ai2").map(ai_parser).unwrap()?;
+        let ai_1 = ai_parser(batch_matches.values_of("ai1").unwrap(), weights, &structure)?;
+        let ai_2 = ai_parser(batch_matches.values_of("ai2").unwrap(), weights, &structure)?;
         let count: usize = batch_matches
             .value_of("count")
             .unwrap()
@@ -46,36 +118,115 @@ pub fn get_arguments() -> Result<Arguments, String> {
             .unwrap();
 
         Ok(Arguments::Batch {
-            structure,
+            start,
             ai_1,
             ai_2,
             count,
         })
     } else if let Some(demo_matches) = matches.subcommand_matches("demo") {
-        let ai_1 = demo_matches.values_of("ai1").map(ai_parser).unwrap()?;
-        let ai_2 = demo_matches.values_of("ai2").map(ai_parser).unwrap()?;
+        let ai_1 = ai_parser(demo_matches.values_of("ai1").unwrap(), weights, &structure)?;
+        let ai_2 = ai_parser(demo_matches.values_of("ai2").unwrap(), weights, &structure)?;
+
+        let evaluator = evaluator_parser(matches.value_of("evaluator"), weights, &structure)?;
 
         Ok(Arguments::Demo {
-            structure,
+            start,
             ai_1,
             ai_2,
+            evaluator,
         })
+    } else if let Some(book_matches) = matches.subcommand_matches("book") {
+        let ai = ai_parser(book_matches.values_of("ai1").unwrap(), weights, &structure)?;
+        let description = book_matches
+            .values_of("ai1")
+            .unwrap()
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let depth = book_matches
+            .value_of("depth")
+            .unwrap()
+            .parse::<u8>()
+            .unwrap();
+        let output = book_matches.value_of("output").unwrap().to_owned();
+
+        Ok(Arguments::Book {
+            start,
+            ai,
+            description,
+            depth,
+            output,
+        })
+    } else if let Some(solve_matches) = matches.subcommand_matches("solve") {
+        let budget = solve_matches
+            .value_of("budget")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+
+        Ok(Arguments::Solve { start, budget })
+    } else if let Some(tune_matches) = matches.subcommand_matches("tune") {
+        let parse = |name| tune_matches.value_of(name).unwrap().parse::<usize>().unwrap();
+        let settings = tune::Settings {
+            depth: parse("depth") as u8,
+            iterations: parse("iterations"),
+            games: parse("games"),
+            step: parse("step") as i32,
+            output: tune_matches.value_of("output").unwrap().to_owned(),
+        };
+
+        Ok(Arguments::Tune {
+            start,
+            weights,
+            settings,
+        })
+    } else if let Some(animate_matches) = matches.subcommand_matches("animate") {
+        let notation = animate_matches.value_of("notation").unwrap();
+        let history = History::from_notation(start, notation)?;
+        let output = animate_matches.value_of("output").unwrap().to_owned();
+
+        Ok(Arguments::Animate { history, output })
     } else if let Some(_) = matches.subcommand_matches("human") {
-        Ok(Arguments::Humans { structure })
+        let evaluator = evaluator_parser(matches.value_of("evaluator"), weights, &structure)?;
+        Ok(Arguments::Humans { start, evaluator })
     } else {
         // No subcommand is activated, this is a normal game VS the AI.
         let opponent = match matches.values_of("opponent") {
-            Some(description) => ai_parser(description),
-            None => Ok(ai::Constructor::MonteCarlo { endurance: 1000 }),
+            Some(description) => ai_parser(description, weights, &structure),
+            None => Ok(ai::Constructor::MonteCarlo {
+                endurance: 1000,
+                tactics: ai::tactics::Tactics::Off,
+                policy: ai::mc::Policy::Uniform,
+            }),
         }?;
 
+        let evaluator = evaluator_parser(matches.value_of("evaluator"), weights, &structure)?;
+
         Ok(Arguments::VsAI {
-            structure,
+            start,
             opponent,
+            evaluator,
         })
     }
 }
 
+// Games start on the empty board unless a position string is given.
+fn starting_position(
+    structure: Arc<game::Structure>,
+    position: Option<&str>,
+) -> Result<game::State, String> {
+    match position {
+        None => Ok(game::State::new(structure)),
+        Some(position) => {
+            let state = game::State::from_position_string(structure, position)?;
+            if state.victory_state.active() {
+                Ok(state)
+            } else {
+                Err("The game is already over in the given position.".to_owned())
+            }
+        }
+    }
+}
+
 fn validate_parse<T: FromStr>(s: String) -> Result<(), String> {
     match s.parse::<T>() {
         Ok(_) => Ok(()),
@@ -83,6 +234,45 @@ fn validate_parse<T: FromStr>(s: String) -> Result<(), String> {
     }
 }
 
+// For counts where 0 makes no sense, like search depths.
+fn validate_positive<T: FromStr + PartialEq + Default>(s: String) -> Result<(), String> {
+    match s.parse::<T>() {
+        Ok(ref value) if *value == T::default() => Err("This argument must not be 0.".to_owned()),
+        Ok(_) => Ok(()),
+        Err(_) => Err("Cannot parse this argument.".to_owned()),
+    }
+}
+
+// Only the mctree AI can analyse positions for the window. The description
+// is a single argument, otherwise it would swallow a following subcommand.
+fn evaluator_parser(
+    description: Option<&str>,
+    weights: ai::value::Weights,
+    structure: &Arc<game::Structure>,
+) -> Result<ai::Constructor, String> {
+    match description {
+        Some(description) => {
+            let values: Vec<&str> = description.split_whitespace().collect();
+            match ai_parser(values.into_iter(), weights, structure)? {
+                evaluator @ ai::Constructor::MonteCarloTree { .. } => Ok(evaluator),
+                _ => Err("The evaluator must be an mctree AI.".to_owned()),
+            }
+        }
+        None => Ok(ai::Constructor::MonteCarloTree {
+            endurance: 4000,
+            exploration: 1.41,
+            tactics: ai::tactics::Tactics::Root,
+            policy: ai::mc::Policy::Uniform,
+            enhancements: ai::mctree::Enhancements::default(),
+        }),
+    }
+}
+
+// Structure files can be invalid in many ways, so the message is passed on.
+fn validate_structure(s: String) -> Result<(), String> {
+    s.parse::<constants::StructureSource>().map(|_| ())
+}
+
 fn setup_clap<'clap>() -> clap::ArgMatches<'clap> {
     let ai_1 = || {
         Arg::with_name("ai1")
@@ -118,6 +308,119 @@ fn setup_clap<'clap>() -> clap::ArgMatches<'clap> {
         .arg(ai_1())
         .arg(ai_2());
 
+    let book_generation = SubCommand::with_name("book")
+        .about("Generates an opening book by analysing all early positions.")
+        .arg(
+            Arg::with_name("depth")
+                .short("d")
+                .long("depth")
+                .help("How many actions the book should cover")
+                .takes_value(true)
+                .default_value("2")
+                .validator(validate_parse::<u8>),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Where the opening book should be stored.")
+                .takes_value(true)
+                .default_value("opening.book"),
+        )
+        .arg(ai_1());
+
+    let solve = SubCommand::with_name("solve")
+        .about("Computes the outcome of the starting position under perfect play.")
+        .arg(
+            Arg::with_name("budget")
+                .short("b")
+                .long("budget")
+                .help("How many positions the solver may visit")
+                .takes_value(true)
+                .default_value("10000000")
+                .validator(validate_parse::<usize>),
+        );
+
+    let tuning = SubCommand::with_name("tune")
+        .about("Optimises the weighted value function by self-play.")
+        .arg(
+            Arg::with_name("iterations")
+                .short("i")
+                .long("iterations")
+                .help("How many parameter changes should be tried")
+                .takes_value(true)
+                .default_value("100")
+                .validator(validate_parse::<usize>),
+        )
+        .arg(
+            Arg::with_name("games")
+                .short("g")
+                .long("games")
+                .help("How many games decide about each change")
+                .takes_value(true)
+                .default_value("10")
+                .validator(validate_parse::<usize>),
+        )
+        .arg(
+            Arg::with_name("depth")
+                .short("d")
+                .long("depth")
+                .help("Search depth of the tree AIs")
+                .takes_value(true)
+                .default_value("2")
+                .validator(validate_positive::<u8>),
+        )
+        .arg(
+            Arg::with_name("step")
+                .long("step")
+                .help("The largest change to a parameter in one iteration")
+                .takes_value(true)
+                .default_value("5")
+                .validator(validate_positive::<u16>),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Where the best parameters should be stored.")
+                .takes_value(true)
+                .default_value("tuned.params"),
+        );
+
+    let diagram = SubCommand::with_name("diagram")
+        .about("Draws the position given by --position as an SVG diagram.")
+        .arg(
+            Arg::with_name("highlight")
+                .long("highlight")
+                .help("Number of a subset to highlight, defaults to the winning subset.")
+                .takes_value(true)
+                .validator(validate_parse::<usize>),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Where the diagram should be stored.")
+                .takes_value(true)
+                .default_value("position.svg"),
+        );
+
+    let animation = SubCommand::with_name("animate")
+        .about("Draws a whole game as an animated SVG diagram.")
+        .arg(
+            Arg::with_name("notation")
+                .required(true)
+                .help("The game in replay notation, e.g. '1. D4 (1) 2. D4 (2)'."),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Where the animation should be stored.")
+                .takes_value(true)
+                .default_value("game.svg"),
+        );
+
     let opponent = Arg::with_name("opponent")
         .short("p")
         .long("player")
@@ -132,16 +435,41 @@ fn setup_clap<'clap>() -> clap::ArgMatches<'clap> {
             Arg::with_name("structure")
                 .short("s")
                 .long("structure")
-                .help("Specify which rules you want to play by.")
+                .help("Specify which rules you want to play by: line, para, plus or a file.")
                 .default_value("line")
-                .validator(validate_parse::<constants::StructureSource>),
+                .validator(validate_structure),
         )
         .subcommand(batch_run)
         .subcommand(demo_match)
+        .subcommand(book_generation)
+        .subcommand(solve)
+        .subcommand(tuning)
+        .subcommand(diagram)
+        .subcommand(animation)
         .subcommand(SubCommand::with_name("human").about(
             "Allow two humans to play against each other.",
         ))
         .arg(opponent)
+        .arg(
+            Arg::with_name("eval-params")
+                .long("eval-params")
+                .help("Parameter file for tree AIs using the 'weighted' value function.")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("evaluator")
+                .long("evaluator")
+                .help("The mctree AI evaluating positions in the window, e.g. 'mctree 4000 1.41 root'.")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("position")
+                .long("position")
+                .help("Start from a position string like '-/-/-/-/-/w/-/-/-/-/-/-/-/-/-/- b'.")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("replay-file")
                 .short("r")
@@ -153,26 +481,52 @@ fn setup_clap<'clap>() -> clap::ArgMatches<'clap> {
 }
 
 
-fn ai_parser(mut values: clap::Values) -> Result<ai::Constructor, String> {
-    let ai_name: &str = values.next().unwrap();
+// The weights are used by the weighted value function, unless the AI
+// description names its own parameter file. Opening books are read for the
+// given structure.
+fn ai_parser<'a, I: ExactSizeIterator<Item = &'a str>>(
+    mut values: I,
+    weights: ai::value::Weights,
+    structure: &Arc<game::Structure>,
+) -> Result<ai::Constructor, String> {
+    let ai_name: &str = values.next().ok_or("No AI specified.")?;
     match ai_name {
-        "random" => Ok(ai::Constructor::Random),
+        "random" => {
+            let tactics = tactics_parser(values.next())?;
+            Ok(ai::Constructor::Random { tactics })
+        }
         "mc" => {
             let endurance = values.next().unwrap_or("10000").parse::<usize>().map_err(
                 |_| "The endurance needs to be a positive integer.",
             )?;
-            Ok(ai::Constructor::MonteCarlo { endurance })
+            let tactics = tactics_parser(values.next())?;
+            let policy = policy_parser(values.next())?;
+            Ok(ai::Constructor::MonteCarlo {
+                endurance,
+                tactics,
+                policy,
+            })
         }
         "tree" => {
             let depth = values.next().unwrap_or("2").parse::<u8>().map_err(
                 |_| "The depth needs to be a small positive integer.",
             )?;
 
-            let value_function = values
+            let value_function = match values
                 .next()
                 .unwrap_or("subsets")
                 .parse::<ai::value::Simple>()
-                .map_err(|_| "Invalid value function provided.")?;
+                .map_err(|_| "Invalid value function provided.")? {
+                ai::value::Simple::Weighted(_) => {
+                    // An optional parameter file overrides --eval-params.
+                    let weights = match values.next() {
+                        Some(path) => ai::value::Weights::load(path)?,
+                        None => weights,
+                    };
+                    ai::value::Simple::Weighted(weights)
+                }
+                value_function => value_function,
+            };
             Ok(ai::Constructor::Tree {
                 depth,
                 value_function,
@@ -187,12 +541,73 @@ fn ai_parser(mut values: clap::Values) -> Result<ai::Constructor, String> {
                 |_| "The exploration needs to be a positive real number.",
             )?;
 
+            let tactics = tactics_parser(values.next())?;
+            let policy = policy_parser(values.next())?;
+
+            // Everything after the policy switches on enhancements.
+            let mut enhancements = ai::mctree::Enhancements::default();
+            for token in values {
+                enhancements.enable(token)?;
+            }
+
             Ok(ai::Constructor::MonteCarloTree {
                 endurance,
                 exploration,
+                tactics,
+                policy,
+                enhancements,
             })
 
         }
+        "book" => {
+            let path = values.next().ok_or("The opening book needs a file.")?;
+            let book = ai::book::OpeningBook::load(path, structure.clone())?;
+            // Everything after the file describes the AI used outside the book.
+            let fallback = ai_parser(values, weights, structure)?;
+
+            Ok(ai::Constructor::Book {
+                book: Arc::new(book),
+                fallback: Box::new(fallback),
+            })
+        }
+        "solver" => {
+            let min_pieces = values.next().unwrap_or("40").parse::<u8>().map_err(
+                |_| "The minimal number of pieces needs to be a small positive integer.",
+            )?;
+            let budget = values.next().unwrap_or("1000000").parse::<usize>().map_err(
+                |_| "The budget needs to be a positive integer.",
+            )?;
+            // Everything after the budget describes the AI used before the
+            // solver takes over.
+            let fallback = if values.len() == 0 {
+                ai::Constructor::MonteCarlo {
+                    endurance: 10000,
+                    tactics: ai::tactics::Tactics::Root,
+                    policy: ai::mc::Policy::Uniform,
+                }
+            } else {
+                ai_parser(values, weights, structure)?
+            };
+
+            Ok(ai::Constructor::Solver {
+                min_pieces,
+                budget,
+                fallback: Box::new(fallback),
+            })
+        }
         _ => Err("AI not recognized.")?,
     }
 }
+
+// The sampling AIs optionally check for forced actions, see ai::tactics.
+fn tactics_parser(value: Option<&str>) -> Result<ai::tactics::Tactics, String> {
+    value.unwrap_or("off").parse::<ai::tactics::Tactics>().map_err(|_| {
+        "The tactics need to be 'off', 'root' or 'heavy'.".to_owned()
+    })
+}
+
+fn policy_parser(value: Option<&str>) -> Result<ai::mc::Policy, String> {
+    value.unwrap_or("uniform").parse::<ai::mc::Policy>().map_err(|_| {
+        "The playout policy needs to be 'uniform', 'winblock' or 'heuristic'.".to_owned()
+    })
+}
@@ -1,11 +1,15 @@
 use game;
+use std::fs::File;
+use std::io::Read;
 use std::str::FromStr;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum StructureSource {
     Line,
     Parallelogram,
     Plus,
+    // Subsets loaded from a file, already validated by Structure::try_new.
+    Custom(Vec<u64>),
 }
 
 impl Into<game::Structure> for StructureSource {
@@ -14,10 +18,12 @@ impl Into<game::Structure> for StructureSource {
             StructureSource::Line => game::Structure::new(&LINES),
             StructureSource::Parallelogram => game::Structure::new(&PARALLELOGRAMS),
             StructureSource::Plus => game::Structure::new(&PLUSSES),
+            StructureSource::Custom(subsets) => game::Structure::new(&subsets),
         }
     }
 }
 
+// Anything but the built-in names is read as a file.
 impl FromStr for StructureSource {
     type Err = String;
 
@@ -27,15 +33,79 @@ impl FromStr for StructureSource {
             "para" => Ok(StructureSource::Parallelogram),
             "parallelogram" => Ok(StructureSource::Parallelogram),
             "plus" => Ok(StructureSource::Plus),
-            _ => Err(format!("Unknown structure name: '{}'", s)),
+            path => StructureSource::load(path).map(StructureSource::Custom),
         }
     }
 }
 
+impl StructureSource {
+    // A structure file lists one subset per line, either as a mask like the
+    // tables below (decimal or hexadecimal with 0x) or as the coordinates
+    // "x,y,z" of its cells, separated by spaces. Coordinates are counted from
+    // 0 and z is the height. Empty lines and lines starting with '#' are
+    // ignored.
+    pub fn load(path: &str) -> Result<Vec<u64>, String> {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .map_err(|err| {
+                format!(
+                    "'{}' is neither line, para, plus nor a structure file: {}",
+                    path,
+                    err
+                )
+            })?;
+
+        let mut subsets = vec![];
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let subset = parse_subset(line)
+                .map_err(|err| format!("Line {} of '{}': {}", index + 1, path, err))?;
+            subsets.push(subset);
+        }
+
+        game::Structure::try_new(&subsets)
+            .map_err(|err| format!("Invalid structure file '{}': {}", path, err))?;
+        Ok(subsets)
+    }
+}
+
+fn parse_subset(line: &str) -> Result<u64, String> {
+    if !line.contains(',') {
+        let parsed = if line.get(..2) == Some("0x") {
+            u64::from_str_radix(&line[2..], 16)
+        } else {
+            line.parse::<u64>()
+        };
+        return parsed.map_err(|_| format!("Invalid mask '{}'.", line));
+    }
+
+    let mut subset = 0u64;
+    for cell in line.split_whitespace() {
+        let coords: Vec<u8> = cell.split(',')
+            .map(|coord| coord.parse::<u8>())
+            .collect::<Result<_, _>>()
+            .unwrap_or_default();
+        if coords.len() != 3 || coords.iter().any(|&coord| coord > 3) {
+            return Err(format!("Invalid cell '{}', expected x,y,z from 0 to 3.", cell));
+        }
+        let position = game::Position3::new(coords[0], coords[1], coords[2]);
+        if subset & (1 << position.0) != 0 {
+            return Err(format!("The cell '{}' is listed twice.", cell));
+        }
+        subset |= 1 << position.0;
+    }
+    Ok(subset)
+}
+
 // this needs to be a unsigned u64 because we are using each of the 64 bits to describe one
 // of the 64 point coordinates of the game state.
 // Here an array must be used because vectors aren't allowed to be constants.
-// The values were generated using a python script which should also be available in the git repo.
+// The values were generated using src/possible_winning_states.py. The generator module builds
+// the same tables from shape templates in Rust, the tests check that both agree.
 pub const PARALLELOGRAMS: [u64; 1020] = [
     4785147619639296,
     9007340990824449,
@@ -1059,8 +1129,8 @@ pub const PARALLELOGRAMS: [u64; 1020] = [
     18760435040256,
 ];
 
-// While the rust code to generate them is reasonable simple and already done it is nice to
-// load them from their number representation to test if everything still works.
+// generator::lines() builds these as well, but it is nice to load them from their number
+// representation to test if everything still works.
 pub const LINES: [u64; 76] = [
     286326784,
     281479271743489,
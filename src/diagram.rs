@@ -0,0 +1,204 @@
+/* Draws positions as SVG diagrams, without opening a window.
+
+The cube is shown as its four layers side by side, the bottom layer on the
+left. Within a layer the columns run from A to D left to right and the rows
+from 1 to 4 bottom to top, as in the replay notation. The cells of a
+highlighted subset get a gold background. Whole games can be drawn as an
+animation. */
+
+use std::fs::File;
+use std::io::Write;
+
+use game;
+use game::{Color, PointState, Position3};
+use replay::History;
+
+const CELL: u32 = 40;
+const MARGIN: u32 = 30;
+const GAP: u32 = 30;
+
+pub const WIDTH: u32 = 2 * MARGIN + 16 * CELL + 3 * GAP;
+pub const HEIGHT: u32 = 2 * MARGIN + 4 * CELL;
+
+pub fn svg(state: &game::State, highlight: Option<game::Subset>) -> String {
+    let mut svg = header();
+    svg.push_str(&grid());
+    svg.push_str(&cells(state, highlight));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+pub fn save(path: &str, svg: &str) -> Result<(), String> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(svg.as_bytes()))
+        .map_err(|err| format!("Can't write the diagram '{}': {}", path, err))
+}
+
+// The subset which won the game, if any.
+pub fn winning_subset(state: &game::State) -> Option<game::Subset> {
+    match state.victory_state {
+        game::VictoryState::Win { reason, .. } => reason,
+        _ => None,
+    }
+}
+
+fn header() -> String {
+    format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"14\">\n\
+         <rect width=\"{0}\" height=\"{1}\" fill=\"#c7a67a\"/>\n",
+        WIDTH,
+        HEIGHT
+    )
+}
+
+// The top left corner of a layer.
+fn layer_origin(z: u8) -> (u32, u32) {
+    (MARGIN + z as u32 * (4 * CELL + GAP), MARGIN)
+}
+
+// The center of a cell.
+fn cell_center(position: Position3) -> (u32, u32) {
+    let (x, y, z) = position.coords();
+    let (left, top) = layer_origin(z);
+    (
+        left + x as u32 * CELL + CELL / 2,
+        top + (3 - y as u32) * CELL + CELL / 2,
+    )
+}
+
+// The layers with their labels. This doesn't depend on the position.
+fn grid() -> String {
+    let mut grid = String::new();
+    for z in 0..4 {
+        let (left, top) = layer_origin(z);
+        grid.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">Layer {}</text>\n",
+            left + 2 * CELL,
+            top - 10,
+            z + 1
+        ));
+        for i in 0..4 {
+            let (x, y) = (left + i * CELL, top + i * CELL);
+            grid.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                x + CELL / 2,
+                top + 4 * CELL + 18,
+                ['A', 'B', 'C', 'D'][i as usize]
+            ));
+            if z == 0 {
+                grid.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                    left - 12,
+                    y + CELL / 2 + 5,
+                    4 - i
+                ));
+            }
+            for j in 0..4 {
+                grid.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" \
+                     fill=\"#dec196\" stroke=\"#7a5c36\"/>\n",
+                    x,
+                    top + j * CELL,
+                    CELL
+                ));
+            }
+        }
+    }
+    grid
+}
+
+// The highlighted cells and the pieces.
+fn cells(state: &game::State, highlight: Option<game::Subset>) -> String {
+    let mut cells = String::new();
+    if let Some(subset) = highlight {
+        for position in subset.iter() {
+            cells.push_str(&highlighted_cell(position, ""));
+        }
+    }
+    for index in 0..64 {
+        let position = Position3(index);
+        if let PointState::Piece(color) = state.at(position) {
+            cells.push_str(&piece(position, color, ""));
+        }
+    }
+    cells
+}
+
+// The elements take an animation as their content.
+fn highlighted_cell(position: Position3, animation: &str) -> String {
+    let (x, y) = cell_center(position);
+    format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{2}\" height=\"{2}\" \
+         fill=\"#ffd84d\" stroke=\"#7a5c36\">{3}</rect>\n",
+        x - CELL / 2,
+        y - CELL / 2,
+        CELL,
+        animation
+    )
+}
+
+fn piece(position: Position3, color: Color, animation: &str) -> String {
+    let (x, y) = cell_center(position);
+    let fill = match color {
+        Color::White => "#ffffff",
+        Color::Black => "#000000",
+    };
+    format!(
+        "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"#000000\">{}</circle>\n",
+        x,
+        y,
+        CELL * 2 / 5,
+        fill,
+        animation
+    )
+}
+
+// Seconds per move and how long the final position is shown.
+const STEP: f32 = 1.0;
+const PAUSE: f32 = 3.0;
+
+/// Shows the game move by move, finishing with the winning subset
+/// highlighted. The animation loops and uses SVG's own animation elements,
+/// viewers without animation support show the final position.
+pub fn animated_svg(history: &History) -> String {
+    let moves: Vec<(Position3, Color)> = history.playback().collect();
+    let duration = moves.len() as f32 * STEP + PAUSE;
+
+    // Hidden until the given second.
+    let appear = |second: f32| {
+        format!(
+            "<animate attributeName=\"opacity\" values=\"0;1\" keyTimes=\"0;{:.4}\" \
+             calcMode=\"discrete\" dur=\"{}s\" repeatCount=\"indefinite\"/>",
+            second / duration,
+            duration
+        )
+    };
+
+    let mut svg = header();
+    svg.push_str(&grid());
+
+    // The winning move completes the subset, so both appear together.
+    let end = moves.len() as f32 * STEP;
+    if let Some(subset) = winning_subset(&history.state) {
+        for position in subset.iter() {
+            svg.push_str(&highlighted_cell(position, &appear(end)));
+        }
+    }
+    // Pieces of the starting position are there from the beginning.
+    for index in 0..64 {
+        let position = Position3(index);
+        if let PointState::Piece(color) = history.state.at(position) {
+            if moves.iter().all(|&(played, _)| played != position) {
+                svg.push_str(&piece(position, color, ""));
+            }
+        }
+    }
+    for (index, &(position, color)) in moves.iter().enumerate() {
+        let second = (index + 1) as f32 * STEP;
+        svg.push_str(&piece(position, color, &appear(second)));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
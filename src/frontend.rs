@@ -0,0 +1,60 @@
+/* This module describes what the game loops in `main` need from a front-end.
+The 3D window in `ui` is one implementation, the scripted front-end below lets
+the same loops run without any window, e.g. in tests. */
+
+use game;
+use game::Position2;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+pub trait Frontend {
+    // Blocks until the player submits an action or the front-end goes away.
+    fn wait_for_action(&self) -> Result<Position2, String>;
+    // Informs the front-end that an action was executed, no matter who played it.
+    fn confirmed_action(&self, action: Position2, color: game::Color) -> Result<(), String>;
+    fn game_over(&self, victory_state: game::VictoryState);
+    // Shows the value of each column for the side to move, None removes the
+    // values again. See ai::mctree::Analysis::overlay.
+    fn show_overlay(&self, overlay: Option<[Option<f32>; 16]>);
+    // Blocks until the front-end is done showing the final position.
+    fn wait_for_halt(&self);
+}
+
+// A front-end without any user interface. The "user" plays a fixed list of
+// actions and everything the game loop reports is recorded for inspection.
+// Currently only used by the tests.
+#[allow(dead_code)]
+pub struct Scripted {
+    actions: RefCell<VecDeque<Position2>>,
+    pub confirmed: RefCell<Vec<(Position2, game::Color)>>,
+    pub result: RefCell<Option<game::VictoryState>>,
+}
+
+impl Scripted {
+    #[allow(dead_code)]
+    pub fn new(actions: Vec<Position2>) -> Self {
+        Scripted {
+            actions: RefCell::new(actions.into_iter().collect()),
+            confirmed: RefCell::new(vec![]),
+            result: RefCell::new(None),
+        }
+    }
+}
+
+impl Frontend for Scripted {
+    fn wait_for_action(&self) -> Result<Position2, String> {
+        self.actions.borrow_mut().pop_front().ok_or_else(|| {
+            "The script ran out of actions.".to_owned()
+        })
+    }
+    fn confirmed_action(&self, action: Position2, color: game::Color) -> Result<(), String> {
+        self.confirmed.borrow_mut().push((action, color));
+        Ok(())
+    }
+    fn game_over(&self, victory_state: game::VictoryState) {
+        *self.result.borrow_mut() = Some(victory_state);
+    }
+    fn show_overlay(&self, _overlay: Option<[Option<f32>; 16]>) {}
+    fn wait_for_halt(&self) {}
+}
@@ -1,6 +1,10 @@
+use std::fmt;
 use std::ops::{AddAssign, Not};
+use std::str::FromStr;
 use std::sync::Arc;
 
+use symmetry::Symmetry;
+
 // The two dimensional position is a number between 0 and 15,
 // the three dimensional position is a number between 0 and 63.
 //
@@ -30,7 +34,6 @@ impl Position2 {
 }
 
 impl Position3 {
-    #[allow(dead_code)]
     pub fn new(x: u8, y: u8, z: u8) -> Self {
         debug_assert!(x <= 3 && y <= 3 && z <= 3);
         Position3(x + 4 * y + 16 * z)
@@ -40,6 +43,40 @@ impl Position3 {
     }
 }
 
+// Columns are written as in the replay notation, e.g. "B3" for x = 1, y = 2.
+impl fmt::Display for Position2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (x, y) = self.coords();
+        write!(f, "{}{}", ['A', 'B', 'C', 'D'][x as usize], y + 1)
+    }
+}
+
+impl FromStr for Position2 {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let x = match chars.next() {
+            Some('A') | Some('a') => 0,
+            Some('B') | Some('b') => 1,
+            Some('C') | Some('c') => 2,
+            Some('D') | Some('d') => 3,
+            _ => return Err(format!("Invalid column '{}', expected A1 to D4.", s)),
+        };
+        let y = match chars.next() {
+            Some('1') => 0,
+            Some('2') => 1,
+            Some('3') => 2,
+            Some('4') => 3,
+            _ => return Err(format!("Invalid column '{}', expected A1 to D4.", s)),
+        };
+        if chars.next().is_some() {
+            return Err(format!("Invalid column '{}', expected A1 to D4.", s));
+        }
+        Ok(Position2::new(x, y))
+    }
+}
+
 impl From<Position3> for Position2 {
     fn from(position3: Position3) -> Position2 {
         Position2(position3.0 % 16)
@@ -225,10 +262,19 @@ pub struct Structure {
     // The size of a victory object. While not technically necessary, having
     // uniform victory objects seems like a reasonable restriction.
     pub object_size: u8,
+    // All symmetries of the board which map winning subsets to winning subsets.
+    pub symmetries: Vec<Symmetry>,
 }
 
 impl Structure {
+    // Panics if the subsets don't form a valid structure, see try_new.
     pub fn new(victory_objects: &[u64]) -> Structure {
+        Structure::try_new(victory_objects).unwrap()
+    }
+    // Checks that there is at least one subset, that all subsets have the
+    // same size and that no subset is listed twice. Subsets are counted from
+    // 1 in the error messages.
+    pub fn try_new(victory_objects: &[u64]) -> Result<Structure, String> {
         use helpers::EqualityVerifier;
 
         // Convert raw u64 into Subset objects. (Which are u64 with extra structure.)
@@ -309,14 +355,37 @@ impl Structure {
                 reverse[position.0 as usize].push(index);
                 subset_size += 1;
             }
+            if subset_size == 0 {
+                return Err(format!("Subset {} is empty.", index + 1));
+            }
+            if source[..index].contains(subset) {
+                return Err(format!("Subset {} is listed twice.", index + 1));
+            }
+            let previous = object_size;
             object_size = object_size.update(subset_size);
+            if let (EqualityVerifier::Value(size), EqualityVerifier::Conflict) =
+                (previous, object_size)
+            {
+                return Err(format!(
+                    "Subset {} has {} cells, the subsets before it have {}.",
+                    index + 1,
+                    subset_size,
+                    size
+                ));
+            }
         }
+        if let EqualityVerifier::NoValue = object_size {
+            return Err("The structure has no subsets.".to_owned());
+        }
+
+        let symmetries = Symmetry::preserving(&source);
 
-        Structure {
+        Ok(Structure {
             source,
             reverse,
             object_size: object_size.unwrap(),
-        }
+            symmetries,
+        })
     }
 }
 
@@ -399,6 +468,150 @@ impl State {
     pub fn column_full(&self, column: Position2) -> bool {
         self.column_height[column.0 as usize] == 4
     }
+    // A compact description of the position, similar to FEN in chess.
+    // The 16 columns are listed in Position2 order and separated by '/'. Each
+    // column lists its pieces from bottom to top as 'w' or 'b', empty columns
+    // are written as '-'. The side to move follows after a space, so the empty
+    // board is "-/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- w".
+    pub fn position_string(&self) -> String {
+        let columns: Vec<String> = (0..16)
+            .map(|column| {
+                let height = self.column_height[column as usize];
+                if height == 0 {
+                    "-".to_owned()
+                } else {
+                    (0..height)
+                        .map(|z| point_letter(self.at(Position2(column).with_height(z))))
+                        .collect()
+                }
+            })
+            .collect();
+        let side = point_letter(PointState::Piece(self.current_color));
+        format!("{} {}", columns.join("/"), side)
+    }
+    // Sets up a position from the output of `position_string`.
+    pub fn from_position_string(
+        structure: Arc<Structure>,
+        position: &str,
+    ) -> Result<State, String> {
+        let mut parts = position.split_whitespace();
+        let columns: Vec<&str> = parts
+            .next()
+            .ok_or("The position string is empty.")?
+            .split('/')
+            .collect();
+        let side = parts.next().ok_or("The side to move is missing.")?;
+        if parts.next().is_some() {
+            return Err("Unexpected text after the side to move.".to_owned());
+        }
+        if columns.len() != 16 {
+            return Err(format!("Expected 16 columns, found {}.", columns.len()));
+        }
+
+        let mut state = State::new(structure);
+        let mut white_count = 0;
+        let mut black_count = 0;
+
+        for (index, column) in columns.iter().enumerate() {
+            if *column == "-" {
+                continue;
+            }
+            if column.is_empty() || column.len() > 4 {
+                return Err(format!("Column {} must hold one to four pieces or be '-'.", index + 1));
+            }
+            for (z, letter) in column.chars().enumerate() {
+                let color = match letter {
+                    'w' => Color::White,
+                    'b' => Color::Black,
+                    _ => return Err(format!("Unknown piece '{}' in column {}.", letter, index + 1)),
+                };
+                match color {
+                    Color::White => white_count += 1,
+                    Color::Black => black_count += 1,
+                }
+                let position = Position2(index as u8).with_height(z as u8);
+                state.points[position.0 as usize] = PointState::Piece(color);
+            }
+            state.column_height[index] = column.len() as u8;
+        }
+
+        state.age = white_count + black_count;
+        state.current_color = match side {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => return Err(format!("The side to move must be 'w' or 'b', not '{}'.", side)),
+        };
+
+        let expected_color = if white_count == black_count {
+            Color::White
+        } else if white_count == black_count + 1 {
+            Color::Black
+        } else {
+            return Err(
+                "White must have the same number of pieces as Black or one more.".to_owned(),
+            );
+        };
+        if expected_color != state.current_color {
+            return Err("The side to move does not match the number of pieces.".to_owned());
+        }
+
+        state.victory_state = state.find_victory_state()?;
+        Ok(state)
+    }
+    // Determines the victory state from scratch. This is only needed when a
+    // position is set up instead of played.
+    fn find_victory_state(&self) -> Result<VictoryState, String> {
+        let mut victory = None;
+        for subset in &self.structure.source {
+            if let LineState::Win(color) = subset.win_state(self) {
+                if color == self.current_color {
+                    return Err("The winner must have made the last move.".to_owned());
+                }
+                victory = Some(VictoryState::Win {
+                    winner: color,
+                    reason: Some(*subset),
+                });
+            }
+        }
+        Ok(match victory {
+            Some(victory_state) => victory_state,
+            None if self.age == 64 => VictoryState::Draw,
+            None => VictoryState::Undecided,
+        })
+    }
+}
+
+fn point_letter(point: PointState) -> char {
+    match point {
+        PointState::Piece(Color::White) => 'w',
+        PointState::Piece(Color::Black) => 'b',
+        PointState::Empty => '.',
+    }
+}
+
+// Draws the four layers side by side, bottom layer first. Inside each layer the
+// columns A-D run from left to right and the rows 1-4 from bottom to top, just
+// like in the replay notation.
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  (1)  (2)  (3)  (4)")?;
+        for y in (0..4).rev() {
+            write!(f, "{}", y + 1)?;
+            for z in 0..4 {
+                write!(f, " ")?;
+                for x in 0..4 {
+                    write!(f, "{}", point_letter(self.at(Position3::new(x, y, z))))?;
+                }
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "  ABCD ABCD ABCD ABCD")?;
+        match self.victory_state {
+            VictoryState::Undecided => write!(f, "{:?} to move.", self.current_color),
+            VictoryState::Win { winner, .. } => write!(f, "{:?} has won.", winner),
+            VictoryState::Draw => write!(f, "The game ended in a draw."),
+        }
+    }
 }
 
 // Once [T; 64] becomes Clone, not just Copy, this can be derived.
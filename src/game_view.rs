@@ -1,14 +1,15 @@
 /* This module is supposed to isolate the state of the board. It should not know
 anything about the camera position and scale. Changing the board state should
-change the state of the 3D scene as well. To make this easier, the whole scene
-is reconstructed whenever the scene changes. */
-use na::{Vector3, Point2, Translation3};
+change the state of the 3D scene as well. The scene is kept between frames and
+compared with the state, so only the nodes which changed are replaced. */
+use na::{Vector3, Point2, Point3, Translation3};
 
 use kiss3d::scene::SceneNode;
 use kiss3d::window::Window;
 use kiss3d::camera::{ArcBall, Camera};
+use kiss3d::text::Font;
 
-use std::sync::Arc;
+use std::rc::Rc;
 
 use game;
 use game::Position2;
@@ -25,20 +26,137 @@ pub enum Phase {
     GameOver(game::VictoryState),
 }
 
+/// Inner cells of the cube are hard to see, so there are several ways to
+/// display the pieces. The view mode only changes how the pieces are drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ViewMode {
+    Solid,
+    // The layers are pulled apart vertically.
+    Exploded,
+    // Pieces are drawn as wireframes.
+    SeeThrough,
+    // Pieces outside the given z-layer are shrunk to small dots.
+    Slice(u8),
+}
+
+impl ViewMode {
+    /// The mode after this one, for cycling with a hotkey.
+    pub fn next(self) -> ViewMode {
+        match self {
+            ViewMode::Solid => ViewMode::Exploded,
+            ViewMode::Exploded => ViewMode::SeeThrough,
+            ViewMode::SeeThrough => ViewMode::Slice(0),
+            ViewMode::Slice(_) => ViewMode::Solid,
+        }
+    }
+
+    fn describe(self) -> Option<String> {
+        match self {
+            ViewMode::Solid => None,
+            ViewMode::Exploded => Some("View: exploded layers".to_owned()),
+            ViewMode::SeeThrough => Some("View: see-through".to_owned()),
+            ViewMode::Slice(layer) => Some(format!("View: layer {} only", layer + 1)),
+        }
+    }
+}
+
+/// Shows the winning subsets of the structure which is played, so players
+/// can learn a variant.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Browser {
+    // The subset shown when no cell is hovered, an index into
+    // Structure::source.
+    pub index: usize,
+    // While a cell is hovered, all subsets through it are shown instead.
+    pub hovered: Option<game::Position3>,
+}
+
+impl Browser {
+    /// The subsets to show, the cells are marked by how many of them they
+    /// belong to.
+    pub fn subsets(&self, structure: &game::Structure) -> Vec<game::Subset> {
+        match self.hovered {
+            Some(cell) => structure.reverse[cell.0 as usize]
+                .iter()
+                .map(|&index| structure.source[index])
+                .collect(),
+            None => vec![structure.source[self.index % structure.source.len()]],
+        }
+    }
+
+    /// Steps through the subsets, `step` is 1 or -1.
+    pub fn step(&mut self, structure: &game::Structure, step: isize) {
+        let count = structure.source.len() as isize;
+        self.index = ((self.index as isize + step) % count + count) as usize % count as usize;
+        self.hovered = None;
+    }
+
+    fn describe(&self, structure: &game::Structure) -> String {
+        match self.hovered {
+            Some(cell) => {
+                let (_, _, z) = cell.coords();
+                format!(
+                    "{} subsets through {} ({})",
+                    structure.reverse[cell.0 as usize].len(),
+                    Position2::from(cell),
+                    z + 1
+                )
+            }
+            None => format!(
+                "Subset {}/{}, press N / P to browse",
+                self.index % structure.source.len() + 1,
+                structure.source.len()
+            ),
+        }
+    }
+}
+
 pub struct State {
     pub replay: History,
     pub hint: Option<Position2>,
     pub phase: Phase,
+    // Keyboard input: The column selected last and the letter of a column
+    // name which is being typed.
+    pub cursor: Position2,
+    pub typed_letter: Option<char>,
+    // Values between -1 and 1 for each column, from the perspective of the
+    // side to move, analysed before its move. They are removed once the
+    // action is played.
+    pub overlay: Option<[Option<f32>; 16]>,
+    // Should the evaluation of the displayed position be shown instead?
+    pub show_evaluation: bool,
+    // Values between -1 and 1 for each column, from the perspective of the
+    // side to move in the displayed position.
+    pub evaluation: Option<[Option<f32>; 16]>,
+    pub view_mode: ViewMode,
+    pub browser: Option<Browser>,
 }
 
 impl State {
-    pub fn empty(structure: Arc<game::Structure>) -> Self {
+    pub fn from_state(start: game::State) -> Self {
         State {
-            replay: History::new(structure),
+            replay: History::from_state(start),
             hint: None,
             phase: Phase::Waiting,
+            cursor: Position2::new(0, 0),
+            typed_letter: None,
+            overlay: None,
+            show_evaluation: false,
+            evaluation: None,
+            view_mode: ViewMode::Solid,
+            browser: None,
         }
+    }
 
+    /// The column values shown on top of the rods. Both the AI's analysis
+    /// and the evaluation rate the columns for the side to move in the
+    /// analysed position, so they share the markers.
+    pub fn column_values(&self) -> Option<[Option<f32>; 16]> {
+        if self.show_evaluation {
+            self.evaluation
+        } else {
+            self.overlay
+        }
     }
 }
 
@@ -51,6 +169,16 @@ fn piece_position(x: i32, y: i32, z: i32) -> Vector3<f32> {
     );
 }
 
+/// Like piece_position, but takes game coordinates and spreads the layers
+/// apart in the exploded view.
+fn piece_target(position: game::Position3, mode: ViewMode) -> Vector3<f32> {
+    let (x, y, z) = position.coords();
+    let mut target = piece_position(x as i32, z as i32, y as i32);
+    if mode == ViewMode::Exploded {
+        target.y += (z as f32) * BALL_DIAMMETER * 0.5;
+    }
+    target
+}
 
 /// Creates the plate with 16 rods.
 /// The scene's origin is located at the center of the plate's top
@@ -93,6 +221,43 @@ pub fn add_hint(scene: &mut SceneNode, position: Position2, color: game::Color)
     piece
 }
 
+/// Marks the top of a rod with a disc coloured from red (-1) to green (1).
+pub fn add_overlay_marker(scene: &mut SceneNode, position: Position2, value: f32) -> SceneNode {
+    let (x, z) = position.coords();
+    let mut marker = scene.add_cylinder(0.2, 0.05);
+    marker.append_translation(&Translation3::new(
+        (x as f32) - 1.5,
+        BALL_DIAMMETER * ROD_LENGTH + 0.05,
+        (z as f32) - 1.5,
+    ));
+    marker.set_color((1.0 - value) / 2.0, (1.0 + value) / 2.0, 0.1);
+
+    marker
+}
+
+/// Marks a cell of a browsed subset. The more of the browsed subsets contain
+/// the cell, the larger and brighter the marker.
+pub fn add_browser_marker(scene: &mut SceneNode, center: Vector3<f32>, share: f32) -> SceneNode {
+    let size = BALL_DIAMMETER * (0.3 + 0.4 * share);
+    let mut marker = scene.add_cube(size, size, size);
+    marker.append_translation(&Translation3::from_vector(center));
+    marker.set_color(0.2, 0.4 + 0.3 * share, 0.6 + 0.4 * share);
+
+    marker
+}
+
+/// The cell of a column which the structure browser shows: The layer of the
+/// single layer view, otherwise the cell where the next piece would land.
+pub fn browsed_cell(state: &State, column: Position2) -> game::Position3 {
+    match state.view_mode {
+        ViewMode::Slice(layer) => column.with_height(layer),
+        _ => {
+            let height = state.replay.state.column_height[column.0 as usize];
+            column.with_height(if height < 3 { height } else { 3 })
+        }
+    }
+}
+
 pub fn placement_coordinate(
     window: &Window,
     camera: &ArcBall,
@@ -113,31 +278,335 @@ pub fn placement_coordinate(
 }
 
 
-pub fn render(target: &mut SceneNode, state: &State) {
-    // First, clear the scene. This is a hack to make Kiss3D "stateless".
-    let mut workaround_node_vec = vec![];
-    target.apply_to_scene_nodes(&mut |node| workaround_node_vec.push(node.clone()));
-    for mut node in workaround_node_vec {
-        node.unlink();
+// Pieces fall with this acceleration, in units per second squared.
+const GRAVITY: f32 = 30.0;
+// How far new pieces fall.
+const FALL_HEIGHT: f32 = BALL_DIAMMETER * 2.0;
+
+// A piece in the scene, possibly still falling down its rod.
+struct Piece {
+    node: SceneNode,
+    color: game::Color,
+    highlighted: bool,
+    // The current height of the center and the falling speed.
+    height: f32,
+    speed: f32,
+}
+
+// Everything besides pieces and the hint is cheap to recreate, so it is
+// rebuilt whenever one of these inputs changes.
+#[derive(Clone, PartialEq)]
+struct DecorationKey {
+    values: Option<[Option<f32>; 16]>,
+    // For each cell, the number of browsed subsets containing it.
+    browsed: Option<[u8; 64]>,
+    mode: ViewMode,
+}
+
+/// The retained 3D scene. Nodes are only added or removed when the displayed
+/// state changes, see Scene::update.
+pub struct Scene {
+    root: SceneNode,
+    pieces: Vec<Option<Piece>>,
+    hint: Option<(Position2, game::Color, SceneNode)>,
+    collar: Option<(game::Position3, SceneNode)>,
+    decorations: Option<(DecorationKey, SceneNode)>,
+    mode: ViewMode,
+}
+
+impl Scene {
+    /// Adds the board to the window scene. The pieces of the starting
+    /// position are placed right away, without falling.
+    pub fn new(window_scene: &mut SceneNode, state: &State) -> Self {
+        let mut root = window_scene.add_group();
+        // Render the empty board. (Baseplate & Sticks)
+        root.add_child(prepare_board());
+
+        let mut scene = Scene {
+            root,
+            pieces: (0..64).map(|_| None).collect(),
+            hint: None,
+            collar: None,
+            decorations: None,
+            mode: state.view_mode,
+        };
+        scene.update_pieces(state, 0.0, false);
+        scene.update(state, 0.0);
+        scene
     }
-    // Render the empty board. (Baseplate & Sticks)
-    target.add_child(prepare_board());
 
-    // Render all pieces currently positioned.
-    for (position, color) in state.replay.playback() {
-        let mut piece = target.add_sphere(BALL_DIAMMETER / 2.0);
-        let (x, y, z) = position.coords();
-        piece.append_translation(&Translation3::from_vector(
-            piece_position(x as i32, z as i32, y as i32),
-        ));
-        match color {
-            game::Color::White => piece.set_color(1.0, 1.0, 1.0),
-            game::Color::Black => piece.set_color(0.0, 0.0, 0.0),
+    /// Brings the scene in line with the state and advances the falling
+    /// animation by `elapsed` seconds.
+    pub fn update(&mut self, state: &State, elapsed: f32) {
+        self.update_mode(state);
+        self.update_pieces(state, elapsed, true);
+        self.update_collar(state);
+        self.update_decorations(state);
+        self.update_hint(state);
+    }
+
+    // New pieces only fall if `animate` is set.
+    fn update_pieces(&mut self, state: &State, elapsed: f32, animate: bool) {
+        // The pieces of a winning subset are rendered in gold.
+        let winning_subset = match state.replay.state.victory_state {
+            game::VictoryState::Win { reason: Some(subset), .. } => subset,
+            _ => game::Subset(0),
+        };
+
+        // This reads the board instead of the playback, because a game may
+        // start from a prepared position.
+        for index in 0..64 {
+            let position = game::Position3(index as u8);
+            let color = match state.replay.state.at(position) {
+                game::PointState::Piece(color) => Some(color),
+                game::PointState::Empty => None,
+            };
+            let highlighted = winning_subset.contains(position);
+
+            let changed = match (self.pieces[index].as_ref(), color) {
+                (Some(piece), Some(color)) => piece.color != color || piece.highlighted != highlighted,
+                (None, None) => false,
+                _ => true,
+            };
+            if changed {
+                // Only pieces which weren't there before fall, recoloured
+                // pieces stay where they are.
+                let falls = animate && self.pieces[index].is_none();
+                if let Some(mut piece) = self.pieces[index].take() {
+                    piece.node.unlink();
+                }
+                if let Some(color) = color {
+                    self.pieces[index] = Some(self.add_piece(position, color, highlighted, falls));
+                }
+            }
+
+            if let Some(ref mut piece) = self.pieces[index] {
+                let target = piece_target(position, self.mode);
+                if piece.height > target.y {
+                    piece.speed += GRAVITY * elapsed;
+                    piece.height = (piece.height - piece.speed * elapsed).max(target.y);
+                }
+                if piece.height <= target.y {
+                    piece.height = target.y;
+                    piece.speed = 0.0;
+                }
+                piece.node.set_local_translation(
+                    Translation3::new(target.x, piece.height, target.z),
+                );
+            }
         }
     }
 
-    // If there is a hint, render it.
-    if let Some(position) = state.hint {
-        add_hint(target, position, state.replay.state.current_color);
+    fn add_piece(
+        &mut self,
+        position: game::Position3,
+        color: game::Color,
+        highlighted: bool,
+        falls: bool,
+    ) -> Piece {
+        let radius = if highlighted { 0.55 } else { 0.5 };
+        let mut node = self.root.add_sphere(BALL_DIAMMETER * radius);
+        match (color, highlighted) {
+            (game::Color::White, false) => node.set_color(1.0, 1.0, 1.0),
+            (game::Color::Black, false) => node.set_color(0.0, 0.0, 0.0),
+            (game::Color::White, true) => node.set_color(1.0, 0.85, 0.3),
+            (game::Color::Black, true) => node.set_color(0.5, 0.35, 0.0),
+        }
+
+        apply_view_mode(&mut node, position, self.mode);
+
+        // New pieces fall from a fixed height above their target, so they
+        // don't jump upwards in the exploded view.
+        let target = piece_target(position, self.mode);
+        let height = if falls {
+            target.y + FALL_HEIGHT
+        } else {
+            target.y
+        };
+
+        Piece {
+            node,
+            color,
+            highlighted,
+            height,
+            speed: 0.0,
+        }
+    }
+
+    // Restyles all pieces when the view mode changes. In the exploded view
+    // the pieces move to their new height at once.
+    fn update_mode(&mut self, state: &State) {
+        if self.mode == state.view_mode {
+            return;
+        }
+        self.mode = state.view_mode;
+        for (index, piece) in self.pieces.iter_mut().enumerate() {
+            if let Some(ref mut piece) = *piece {
+                let position = game::Position3(index as u8);
+                apply_view_mode(&mut piece.node, position, self.mode);
+                piece.height = piece_target(position, self.mode).y;
+                piece.speed = 0.0;
+            }
+        }
+    }
+
+    // Puts a red collar around the piece which was placed last. The collar
+    // is attached to the piece, so it falls along.
+    fn update_collar(&mut self, state: &State) {
+        let last_placement = state.replay.last_placement();
+        if self.collar.as_ref().map(|&(position, _)| position) == last_placement {
+            return;
+        }
+        if let Some((_, mut collar)) = self.collar.take() {
+            collar.unlink();
+        }
+        if let Some(position) = last_placement {
+            if let Some(ref mut piece) = self.pieces[position.0 as usize] {
+                let mut collar = piece.node.add_cylinder(BALL_DIAMMETER * 0.52, 0.06);
+                collar.set_color(0.8, 0.1, 0.1);
+                self.collar = Some((position, collar));
+            }
+        }
+    }
+
+    fn update_decorations(&mut self, state: &State) {
+        let key = DecorationKey {
+            values: state.column_values(),
+            browsed: state.browser.map(|browser| {
+                let mut counts = [0; 64];
+                for subset in browser.subsets(&state.replay.state.structure) {
+                    for cell in subset.iter() {
+                        counts[cell.0 as usize] += 1;
+                    }
+                }
+                counts
+            }),
+            mode: self.mode,
+        };
+        if self.decorations.as_ref().map(|decorations| &decorations.0) == Some(&key) {
+            return;
+        }
+        if let Some((_, mut group)) = self.decorations.take() {
+            group.unlink();
+        }
+
+        let mut group = self.root.add_group();
+        if let Some(values) = key.values {
+            for (index, value) in values.iter().enumerate() {
+                if let Some(value) = *value {
+                    add_overlay_marker(&mut group, Position2(index as u8), value);
+                }
+            }
+        }
+        if let Some(browsed) = key.browsed {
+            let most = browsed.iter().cloned().max().unwrap_or(0);
+            for (index, &count) in browsed.iter().enumerate() {
+                if count > 0 {
+                    let position = game::Position3(index as u8);
+                    let share = count as f32 / most as f32;
+                    add_browser_marker(&mut group, piece_target(position, key.mode), share);
+                }
+            }
+        }
+        self.decorations = Some((key, group));
+    }
+
+    fn update_hint(&mut self, state: &State) {
+        let hint = state.hint.map(|position| (position, state.replay.state.current_color));
+        if self.hint.as_ref().map(|&(position, color, _)| (position, color)) == hint {
+            return;
+        }
+        if let Some((_, _, mut node)) = self.hint.take() {
+            node.unlink();
+        }
+        if let Some((position, color)) = hint {
+            let node = add_hint(&mut self.root, position, color);
+            self.hint = Some((position, color, node));
+        }
+    }
+}
+
+// kiss3d can't blend, so "transparent" pieces are drawn as wireframes.
+fn apply_view_mode(node: &mut SceneNode, position: game::Position3, mode: ViewMode) {
+    let (_, _, z) = position.coords();
+    match mode {
+        ViewMode::SeeThrough => {
+            node.set_surface_rendering_activation(false);
+            node.set_lines_width(1.0);
+        }
+        _ => {
+            node.set_surface_rendering_activation(true);
+            node.set_lines_width(0.0);
+        }
+    }
+    match mode {
+        ViewMode::Slice(layer) if layer != z => node.set_local_scale(0.3, 0.3, 0.3),
+        _ => node.set_local_scale(1.0, 1.0, 1.0),
+    }
+}
+
+/// Lines of text describing the displayed position.
+pub fn hud(state: &State) -> Vec<String> {
+    let game_state = &state.replay.state;
+    let mut lines = vec![];
+
+    if game_state.victory_state.active() {
+        lines.push(format!(
+            "Move {}, {:?} to move",
+            game_state.age + 1,
+            game_state.current_color
+        ));
+    }
+    if let Some(position) = state.replay.last_placement() {
+        let (_, _, z) = position.coords();
+        lines.push(format!("Last move: {} ({})", Position2::from(position), z + 1));
+    }
+    if let Some((shown, total)) = state.replay.playback_progress() {
+        lines.push(format!("Playback {}/{}, press Space to resume", shown, total));
+    } else if state.phase == Phase::Waiting && game_state.victory_state.active() {
+        lines.push("AI thinking...".to_owned());
+    }
+    if let Some(description) = state.view_mode.describe() {
+        lines.push(description);
+    }
+    if let Some(browser) = state.browser {
+        lines.push(browser.describe(&game_state.structure));
+    }
+    if let Some(letter) = state.typed_letter {
+        lines.push(format!("Column {}?", letter));
+    }
+
+    lines
+}
+
+/// Draws the HUD lines and the game-over banner in the top left corner.
+pub fn render_text(window: &mut Window, font: &Rc<Font>, state: &State) {
+    let white = Point3::new(1.0, 1.0, 1.0);
+    let gold = Point3::new(1.0, 0.85, 0.3);
+    let mut y = 20.0;
+
+    for line in hud(state) {
+        window.draw_text(&line, &Point2::new(20.0, y), font, &white);
+        y += 50.0;
+    }
+    if let Some(banner) = banner(state) {
+        window.draw_text(&banner, &Point2::new(20.0, y), font, &gold);
+    }
+}
+
+/// The HUD lines and the banner for the terminal, used if no font is
+/// available.
+pub fn plain_text(state: &State) -> String {
+    let mut lines = hud(state);
+    lines.extend(banner(state));
+    lines.join("\n")
+}
+
+/// The text shown once the displayed position is decided.
+pub fn banner(state: &State) -> Option<String> {
+    match state.replay.state.victory_state {
+        game::VictoryState::Win { winner, .. } => Some(format!("{:?} has won!", winner)),
+        game::VictoryState::Draw => Some("The game ends in a draw.".to_owned()),
+        game::VictoryState::Undecided => None,
     }
 }
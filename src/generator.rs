@@ -0,0 +1,197 @@
+/* Generates victory structures from shape templates.
+
+A template is a set of cells relative to an arbitrary origin. Each symmetry of
+the chosen group maps the template to a new shape, which is then moved to all
+places where it fits into the 4x4x4 board. Every placement becomes a Subset,
+placements which cover the same cells are only counted once.
+
+The built-in tables in constants.rs can be regenerated this way, see the
+templates below and the tests. */
+
+use std::collections::HashSet;
+
+use game;
+
+// Offsets may be negative, they are only moved into the board later.
+pub type Offset = (i8, i8, i8);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Symmetries {
+    // All 48 rotations and reflections of the cube, i.e. the axes may be
+    // permuted and each may be flipped.
+    Cube,
+    // The 8 rotations and reflections which keep the z axis pointing up.
+    Horizontal,
+    // Only moves the template around.
+    Translations,
+}
+
+// Maps an offset by permuting the axes and flipping their signs.
+#[derive(Copy, Clone, Debug)]
+struct Transformation {
+    permutation: [usize; 3],
+    signs: [i8; 3],
+}
+
+impl Transformation {
+    fn apply(self, offset: Offset) -> Offset {
+        let coords = [offset.0, offset.1, offset.2];
+        (
+            self.signs[0] * coords[self.permutation[0]],
+            self.signs[1] * coords[self.permutation[1]],
+            self.signs[2] * coords[self.permutation[2]],
+        )
+    }
+}
+
+impl Symmetries {
+    fn transformations(self) -> Vec<Transformation> {
+        let permutations: Vec<[usize; 3]> = match self {
+            Symmetries::Cube => vec![
+                [0, 1, 2],
+                [0, 2, 1],
+                [1, 0, 2],
+                [1, 2, 0],
+                [2, 0, 1],
+                [2, 1, 0],
+            ],
+            Symmetries::Horizontal => vec![[0, 1, 2], [1, 0, 2]],
+            Symmetries::Translations => vec![[0, 1, 2]],
+        };
+        let z_signs: &[i8] = match self {
+            Symmetries::Cube => &[1, -1],
+            _ => &[1],
+        };
+        let xy_signs: &[i8] = match self {
+            Symmetries::Translations => &[1],
+            _ => &[1, -1],
+        };
+
+        let mut result = vec![];
+        for &permutation in &permutations {
+            for &x in xy_signs {
+                for &y in xy_signs {
+                    for &z in z_signs {
+                        result.push(Transformation {
+                            permutation,
+                            signs: [x, y, z],
+                        });
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+// The 26 directions from a cell to its neighbours.
+pub fn directions() -> Vec<Offset> {
+    let mut result = vec![];
+    for x in -1..2 {
+        for y in -1..2 {
+            for z in -1..2 {
+                if (x, y, z) != (0, 0, 0) {
+                    result.push((x, y, z));
+                }
+            }
+        }
+    }
+    result
+}
+
+// Up to symmetries of the cube, these are the only directions.
+fn axis_directions() -> Vec<Offset> {
+    vec![(1, 0, 0), (1, 1, 0), (1, 1, 1)]
+}
+
+// Four in a row along an axis, a face diagonal or a space diagonal.
+pub fn lines() -> Vec<Vec<Offset>> {
+    axis_directions()
+        .into_iter()
+        .map(|(x, y, z)| (0..4).map(|i| (i * x, i * y, i * z)).collect())
+        .collect()
+}
+
+// Three steps along v and two along w, if v and w aren't parallel.
+pub fn parallelograms() -> Vec<Vec<Offset>> {
+    let mut result = vec![];
+    for v in axis_directions() {
+        for w in directions() {
+            if w == v || w == (-v.0, -v.1, -v.2) {
+                continue;
+            }
+            let mut template = vec![];
+            for i in 0..3 {
+                for j in 0..2 {
+                    template.push((i * v.0 + j * w.0, i * v.1 + j * w.1, i * v.2 + j * w.2));
+                }
+            }
+            result.push(template);
+        }
+    }
+    result
+}
+
+// A center with two neighbours along v and two along w, v and w orthogonal.
+pub fn plusses() -> Vec<Vec<Offset>> {
+    let mut result = vec![];
+    for v in axis_directions() {
+        for w in directions() {
+            if v.0 * w.0 + v.1 * w.1 + v.2 * w.2 != 0 {
+                continue;
+            }
+            result.push(vec![(0, 0, 0), v, (-v.0, -v.1, -v.2), w, (-w.0, -w.1, -w.2)]);
+        }
+    }
+    result
+}
+
+// All placements of all templates on the board, as subset masks. Templates
+// with repeated offsets produce smaller subsets, Structure::try_new rejects
+// those if the sizes differ.
+pub fn generate(templates: &[Vec<Offset>], symmetries: Symmetries) -> Vec<u64> {
+    let mut seen = HashSet::new();
+    let mut result = vec![];
+
+    for template in templates {
+        for transformation in symmetries.transformations() {
+            let image: Vec<Offset> = template.iter().map(|&o| transformation.apply(o)).collect();
+            if image.is_empty() {
+                continue;
+            }
+            // Moves the image so its lowest coordinates become 0, the
+            // remaining room decides how far it can be moved.
+            let low = (
+                image.iter().map(|o| o.0).min().unwrap(),
+                image.iter().map(|o| o.1).min().unwrap(),
+                image.iter().map(|o| o.2).min().unwrap(),
+            );
+            let high = (
+                image.iter().map(|o| o.0).max().unwrap(),
+                image.iter().map(|o| o.1).max().unwrap(),
+                image.iter().map(|o| o.2).max().unwrap(),
+            );
+            for dx in -low.0..4 - high.0 {
+                for dy in -low.1..4 - high.1 {
+                    for dz in -low.2..4 - high.2 {
+                        let mask = image.iter().fold(0u64, |mask, &(x, y, z)| {
+                            let (x, y, z) = ((x + dx) as u8, (y + dy) as u8, (z + dz) as u8);
+                            mask | (1 << game::Position3::new(x, y, z).0)
+                        });
+                        if seen.insert(mask) {
+                            result.push(mask);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+pub fn structure(
+    templates: &[Vec<Offset>],
+    symmetries: Symmetries,
+) -> Result<game::Structure, String> {
+    game::Structure::try_new(&generate(templates, symmetries))
+}
@@ -3,12 +3,20 @@ mod tests;
 
 mod game;
 mod ai;
+mod frontend;
 mod ui;
 mod game_view;
 mod constants;
 mod helpers;
 mod thread_synchronisation;
 mod replay;
+mod symmetry;
+mod tune;
+mod diagram;
+// Only the tests use the generator so far, the tables in constants.rs are
+// faster to load.
+#[allow(dead_code)]
+mod generator;
 mod command_line;
 
 // UI dependencies
@@ -22,7 +30,7 @@ extern crate clap;
 extern crate threadpool;
 
 use ai::StatelessAI;
-use std::sync::Arc;
+use frontend::Frontend;
 
 fn main() {
     use command_line::Arguments;
@@ -39,42 +47,103 @@ fn main() {
 
     let replay = match argument {
         Arguments::VsAI {
-            structure,
+            start,
             opponent,
-        } => interactive(Arc::new(structure.into()), ai::AIBox::new(opponent)),
+            evaluator,
+        } => {
+            interactive(
+                &ui::UiConnector::new(start.clone(), evaluator),
+                replay::History::from_state(start),
+                ai::AIBox::new(opponent),
+            )
+        }
         Arguments::Batch {
-            structure,
+            start,
             count,
             ai_1,
             ai_2,
         } => {
-            batch(
-                Arc::new(structure.into()),
-                count,
-                ai::AIBox::new(ai_1),
-                ai::AIBox::new(ai_2),
-            );
+            batch(start, count, ai::AIBox::new(ai_1), ai::AIBox::new(ai_2));
             // FIXME: Store the replays. Maybe batch can return an Iterator of
             // type History? Or just do the counting in this loop.
             return;
         }
         Arguments::Demo {
-            structure,
+            start,
             ai_1,
             ai_2,
+            evaluator,
         } => {
             demo(
-                Arc::new(structure.into()),
+                &ui::UiConnector::new(start.clone(), evaluator),
+                replay::History::from_state(start),
                 ai::AIBox::new(ai_1),
                 ai::AIBox::new(ai_2),
             )
         }
-        Arguments::Humans { structure } => humans(Arc::new(structure.into())),
+        Arguments::Book {
+            start,
+            ai,
+            description,
+            depth,
+            output,
+        } => {
+            let book = ai::book::generate(&start, depth, &ai);
+            let header = format!(
+                "{} positions up to depth {}, analysed by '{}'",
+                book.len(),
+                depth,
+                description
+            );
+            match book.save(&output, &header) {
+                Ok(()) => println!("Stored {} positions in {}.", book.len(), output),
+                Err(err) => println!("{}", err),
+            }
+            return;
+        }
+        Arguments::Solve { start, budget } => {
+            solve(start, budget);
+            return;
+        }
+        Arguments::Tune {
+            start,
+            weights,
+            settings,
+        } => {
+            let best = tune::tune(&start, weights, &settings);
+            println!("Best parameters found:\n{}", best);
+            return;
+        }
+        Arguments::Diagram {
+            state,
+            highlight,
+            output,
+        } => {
+            match diagram::save(&output, &diagram::svg(&state, highlight)) {
+                Ok(()) => println!("Stored the diagram in {}.", output),
+                Err(err) => println!("{}", err),
+            }
+            return;
+        }
+        Arguments::Animate { history, output } => {
+            match diagram::save(&output, &diagram::animated_svg(&history)) {
+                Ok(()) => println!("Stored the animation in {}.", output),
+                Err(err) => println!("{}", err),
+            }
+            return;
+        }
+        Arguments::Humans { start, evaluator } => {
+            humans(
+                &ui::UiConnector::new(start.clone(), evaluator),
+                replay::History::from_state(start),
+            )
+        }
     };
 
     // TODO: Store this in a file instead.
     // (Actually use the supplied replay path.)
     println!("{}", replay.notation());
+    println!("Final position: {}", replay.state.position_string());
     // let replay_path = matches.value_of("replay-file").unwrap();
 
 
@@ -83,13 +152,13 @@ fn main() {
 
 
 
-fn interactive(structure: Arc<game::Structure>, mut p2: ai::AIBox) -> replay::History {
-    let ui_connector = ui::UiConnector::new(structure.clone());
-
-    let mut replay = replay::History::new(structure.clone());
-
+fn interactive<F: Frontend>(
+    ui_connector: &F,
+    mut replay: replay::History,
+    mut p2: ai::AIBox,
+) -> replay::History {
     loop {
-        user_turn(&ui_connector, &mut replay);
+        user_turn(ui_connector, &mut replay);
 
         // Check for victory.
         if !replay.state.victory_state.active() {
@@ -99,7 +168,7 @@ fn interactive(structure: Arc<game::Structure>, mut p2: ai::AIBox) -> replay::Hi
             break;
         }
 
-        ai_turn(&ui_connector, &mut p2, &mut replay);
+        ai_turn(ui_connector, &mut p2, &mut replay);
 
         // Check for victory.
         if !replay.state.victory_state.active() {
@@ -113,7 +182,7 @@ fn interactive(structure: Arc<game::Structure>, mut p2: ai::AIBox) -> replay::Hi
     replay
 }
 
-fn user_turn(ui_connector: &ui::UiConnector, replay: &mut replay::History) {
+fn user_turn<F: Frontend>(ui_connector: &F, replay: &mut replay::History) {
     // Wait for the player to make the first action
     let action = ui_connector.wait_for_action().unwrap();
 
@@ -122,8 +191,8 @@ fn user_turn(ui_connector: &ui::UiConnector, replay: &mut replay::History) {
     ui_connector.confirmed_action(action, color).unwrap();
 }
 
-fn ai_turn<A: StatelessAI>(
-    ui_connector: &ui::UiConnector,
+fn ai_turn<F: Frontend, A: StatelessAI>(
+    ui_connector: &F,
     ai: &mut A,
     replay: &mut replay::History,
 ) {
@@ -137,18 +206,29 @@ fn ai_turn<A: StatelessAI>(
 
 
 // This is simmilar to interactive, but the player isn't allowed to do any moves.
-fn demo(
-    structure: Arc<game::Structure>,
+fn demo<F: Frontend>(
+    ui_connector: &F,
+    mut replay: replay::History,
     mut active_ai: ai::AIBox,
     mut waiting_ai: ai::AIBox,
 ) -> replay::History {
     use std::mem::swap;
-    let ui_connector = ui::UiConnector::new(structure.clone());
-
-    let mut replay = replay::History::new(structure.clone());
 
     loop {
-        ai_turn(&ui_connector, &mut active_ai, &mut replay);
+        // AIs which can explain their decision show the value of each column.
+        match active_ai.analyse(&replay.state) {
+            Some(analysis) => {
+                println!("{}", analysis);
+                ui_connector.show_overlay(Some(analysis.overlay()));
+
+                let color = replay.state.current_color;
+                replay.add(analysis.best);
+                ui_connector.confirmed_action(analysis.best, color).unwrap();
+                // The values are only valid for the side which just moved.
+                ui_connector.show_overlay(None);
+            }
+            None => ai_turn(ui_connector, &mut active_ai, &mut replay),
+        }
 
         // Check for victory.
         if !replay.state.victory_state.active() {
@@ -167,23 +247,35 @@ fn demo(
 
 /* Batch mode allows you to pitch two AIs against each other
 and get some information what happened in the game. */
-fn batch(structure: Arc<game::Structure>, count: usize, mut ai_1: ai::AIBox, mut ai_2: ai::AIBox) {
+fn batch(start: game::State, count: usize, mut ai_1: ai::AIBox, mut ai_2: ai::AIBox) {
     for i in 1..count + 1 {
         println!(
             "Match {} results in {:?}",
             i,
-            ai::run_match(structure.clone(), &mut ai_1, &mut ai_2).victory_state
+            ai::run_match_from(start.clone(), &mut ai_1, &mut ai_2).victory_state
         );
     }
 }
 
-fn humans(structure: Arc<game::Structure>) -> replay::History {
-    let ui_connector = ui::UiConnector::new(structure.clone());
+fn solve(start: game::State, budget: usize) {
+    println!("{}", start);
 
-    let mut replay = replay::History::new(structure.clone());
+    let mut solver = ai::solver::Solver::new(&start.structure, budget);
+    match solver.solve(&start) {
+        Some((outcome, action)) => {
+            println!("{}", ai::solver::describe(outcome, start.current_color));
+            if let Some(action) = action {
+                println!("Best action: {}", action);
+            }
+        }
+        None => println!("The solver ran out of its budget of {} positions.", budget),
+    }
+    println!("Visited {} positions.", solver.nodes());
+}
 
+fn humans<F: Frontend>(ui_connector: &F, mut replay: replay::History) -> replay::History {
     loop {
-        user_turn(&ui_connector, &mut replay);
+        user_turn(ui_connector, &mut replay);
 
         // Check for victory.
         if !replay.state.victory_state.active() {
@@ -7,15 +7,22 @@ use std::sync::Arc;
 pub struct History {
     actions: Vec<Position2>,
     playback_count: Option<usize>,
+    // The position before the first action, usually the empty board.
+    initial: game::State,
     pub state: game::State,
 }
 
 impl History {
+    #[allow(dead_code)]
     pub fn new(structure: Arc<game::Structure>) -> Self {
+        History::from_state(game::State::new(structure))
+    }
+    pub fn from_state(state: game::State) -> Self {
         History {
             actions: vec![],
             playback_count: None,
-            state: game::State::new(structure),
+            initial: state.clone(),
+            state,
         }
     }
     pub fn add(&mut self, action: Position2) {
@@ -32,7 +39,7 @@ impl History {
         let new_count = current_count - 1;
         self.playback_count = Some(new_count);
 
-        self.state = game::State::new(self.state.structure.clone());
+        self.state = self.initial.clone();
         for i in 0..new_count {
             self.state.execute(self.actions[i]);
         }
@@ -64,6 +71,15 @@ impl History {
     pub fn is_resumed(&self) -> bool {
         self.playback_count.is_none()
     }
+    // While browsing the history, returns how many actions are shown and how
+    // many there are in total.
+    pub fn playback_progress(&self) -> Option<(usize, usize)> {
+        self.playback_count.map(|count| (count, self.actions.len()))
+    }
+    // Where the most recent piece of the displayed position was placed.
+    pub fn last_placement(&self) -> Option<game::Position3> {
+        self.playback().last().map(|(position, _)| position)
+    }
     pub fn playback(&self) -> HistoryPlayback {
         let max_index = self.playback_count.unwrap_or(self.actions.len());
 
@@ -71,20 +87,81 @@ impl History {
             index: 0,
             max_index,
             actions: &self.actions,
-            state: game::State::new(self.state.structure.clone()),
+            state: self.initial.clone(),
         }
     }
     pub fn notation(&self) -> String {
         let mut buffer = String::new();
 
+        // Games set up from a position continue the move numbers.
+        let offset = self.initial.age as usize;
+
         for (index, (position, _)) in self.playback().enumerate() {
-            let (x, y, z) = position.coords();
-            let x_letter = ['A', 'B', 'C', 'D'][x as usize];
-            buffer += &format!("{}. {}{} ({}) ", index + 1, x_letter, y + 1, z + 1);
+            let (_, _, z) = position.coords();
+            let column = Position2::from(position);
+            buffer += &format!("{}. {} ({}) ", offset + index + 1, column, z + 1);
         }
 
         buffer
     }
+    // Reads the output of `notation`, starting from the given position. Move
+    // numbers and heights are optional, but they must match if given.
+    pub fn from_notation(start: game::State, notation: &str) -> Result<Self, String> {
+        let offset = start.age as usize;
+        let mut history = History::from_state(start);
+
+        for token in notation.split_whitespace() {
+            let count = history.actions.len();
+            // Heights are written like "(2)", move numbers like "5.".
+            let mut inner = token.chars();
+            let height = if inner.next() == Some('(') && inner.next_back() == Some(')') {
+                Some(inner.as_str())
+            } else {
+                None
+            };
+            let mut number = token.chars();
+            if number.next_back() == Some('.') {
+                let number = number
+                    .as_str()
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid move number '{}'.", token))?;
+                if number != offset + count + 1 {
+                    return Err(format!(
+                        "Expected move number {}, found {}.",
+                        offset + count + 1,
+                        number
+                    ));
+                }
+            } else if let Some(height) = height {
+                let height = height
+                    .parse::<u8>()
+                    .map_err(|_| format!("Invalid height '{}'.", token))?;
+                let (_, _, z) = history
+                    .last_placement()
+                    .ok_or_else(|| format!("The height {} belongs to no move.", token))?
+                    .coords();
+                if height != z + 1 {
+                    return Err(format!(
+                        "Move {} lands at height {}, not {}.",
+                        offset + count,
+                        z + 1,
+                        height
+                    ));
+                }
+            } else {
+                let column = token.parse::<Position2>()?;
+                if !history.state.victory_state.active() {
+                    return Err(format!("Move {} comes after the end of the game.", offset + count + 1));
+                }
+                if history.state.column_full(column) {
+                    return Err(format!("Move {}: column {} is full.", offset + count + 1, column));
+                }
+                history.add(column);
+            }
+        }
+
+        Ok(history)
+    }
 }
 
 pub struct HistoryPlayback<'a> {
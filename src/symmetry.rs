@@ -0,0 +1,168 @@
+/* This module implements the symmetries of the board.
+
+Gravity singles out the z axis, so only transformations of the horizontal
+Position2 grid are allowed. These are the eight rotations and reflections of
+the square, each optionally combined with the inside-out swap which exchanges
+the inner and outer rows (0 <-> 1, 2 <-> 3) along both horizontal axes.
+
+Not every one of those 16 transformations maps winning subsets to winning
+subsets. Which ones do depends on the Structure, so the Structure stores the
+list of symmetries that preserve it. */
+
+use std::collections::HashSet;
+
+use game::{Position2, Position3, PointState, Subset, State, VictoryState, Color};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Symmetry {
+    // Number of quarter turns around the vertical axis, applied last.
+    rotation: u8,
+    // Mirror the x coordinate before rotating.
+    mirror: bool,
+    // Swap inner and outer rows along both axes, applied first.
+    inside_out: bool,
+}
+
+impl Symmetry {
+    pub fn identity() -> Self {
+        Symmetry {
+            rotation: 0,
+            mirror: false,
+            inside_out: false,
+        }
+    }
+    // All 16 candidate symmetries, starting with the identity.
+    pub fn all() -> Vec<Symmetry> {
+        let mut result = vec![];
+        for &inside_out in &[false, true] {
+            for &mirror in &[false, true] {
+                for rotation in 0..4 {
+                    result.push(Symmetry {
+                        rotation,
+                        mirror,
+                        inside_out,
+                    });
+                }
+            }
+        }
+        result
+    }
+    // Returns all symmetries which map winning subsets to winning subsets.
+    pub fn preserving(source: &[Subset]) -> Vec<Symmetry> {
+        let subsets: HashSet<u64> = source.iter().map(|subset| subset.0).collect();
+        Symmetry::all()
+            .into_iter()
+            .filter(|symmetry| {
+                source.iter().all(|subset| {
+                    subsets.contains(&symmetry.apply_subset(*subset).0)
+                })
+            })
+            .collect()
+    }
+    pub fn apply_position2(self, position: Position2) -> Position2 {
+        let (mut x, mut y) = position.coords();
+        if self.inside_out {
+            x ^= 1;
+            y ^= 1;
+        }
+        if self.mirror {
+            x = 3 - x;
+        }
+        for _ in 0..self.rotation {
+            let old_x = x;
+            x = 3 - y;
+            y = old_x;
+        }
+        Position2::new(x, y)
+    }
+    pub fn apply_position3(self, position: Position3) -> Position3 {
+        let (_, _, z) = position.coords();
+        self.apply_position2(position.into()).with_height(z)
+    }
+    pub fn apply_subset(self, subset: Subset) -> Subset {
+        Subset(subset.iter().fold(0, |mask, position| {
+            mask | 1 << self.apply_position3(position).0
+        }))
+    }
+    pub fn apply_state(self, state: &State) -> State {
+        let mut result = state.clone();
+        for index in 0..64 {
+            let target = self.apply_position3(Position3(index));
+            result.points[target.0 as usize] = state.points[index as usize];
+        }
+        for index in 0..16 {
+            let target = self.apply_position2(Position2(index));
+            result.column_height[target.0 as usize] = state.column_height[index as usize];
+        }
+        if let VictoryState::Win {
+            winner,
+            reason: Some(subset),
+        } = state.victory_state
+        {
+            result.victory_state = VictoryState::Win {
+                winner,
+                reason: Some(self.apply_subset(subset)),
+            };
+        }
+        result
+    }
+    // The symmetry which undoes this one.
+    pub fn inverse(self) -> Symmetry {
+        Symmetry::all()
+            .into_iter()
+            .find(|candidate| {
+                (0..16).all(|index| {
+                    let position = Position2(index);
+                    candidate.apply_position2(self.apply_position2(position)) == position
+                })
+            })
+            .unwrap()
+    }
+}
+
+// Packs the pieces of both colors into two bitboards. This is used to compare
+// positions, the smallest key wins the race to be canonical.
+fn position_key(state: &State) -> (u64, u64) {
+    let mut white = 0;
+    let mut black = 0;
+    for (index, point) in state.points.iter().enumerate() {
+        match *point {
+            PointState::Piece(Color::White) => white |= 1 << index,
+            PointState::Piece(Color::Black) => black |= 1 << index,
+            PointState::Empty => {}
+        }
+    }
+    (white, black)
+}
+
+// Maps the state to a representative which is the same for all positions
+// equivalent under the symmetries of its structure. The returned symmetry
+// transforms the given state into the canonical one.
+pub fn canonical_form(state: &State) -> (State, Symmetry) {
+    let mut best_symmetry = Symmetry::identity();
+    let mut best_key = position_key(state);
+
+    for &symmetry in &state.structure.symmetries {
+        let key = position_key(&symmetry.apply_state(state));
+        if key < best_key {
+            best_key = key;
+            best_symmetry = symmetry;
+        }
+    }
+
+    (best_symmetry.apply_state(state), best_symmetry)
+}
+
+#[test]
+fn test_symmetry_inverse() {
+    for symmetry in Symmetry::all() {
+        let inverse = symmetry.inverse();
+        for index in 0..64 {
+            let position = Position3(index);
+            assert_eq!(
+                position,
+                inverse.apply_position3(symmetry.apply_position3(position))
+            );
+        }
+    }
+}
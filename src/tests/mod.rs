@@ -1,24 +1,27 @@
 #[cfg(test)]
 use game;
 use ai;
+use symmetry;
 use ai::run_match;
+use ai::mc::Policy;
+use ai::tactics::Tactics;
 use constants::LINES;
 use std::sync::Arc;
 
 #[test]
 fn match_mc() {
     let structure = Arc::new(game::Structure::new(&LINES));
-    let mut white_player = ai::mc::MonteCarloAI::new(1000);
-    let mut black_player = ai::mc::MonteCarloAI::new(1000);
+    let mut white_player = ai::mc::MonteCarloAI::new(1000, Tactics::Off, Policy::Uniform);
+    let mut black_player = ai::mc::MonteCarloAI::new(1000, Tactics::Off, Policy::Uniform);
     run_match(structure, &mut white_player, &mut black_player);
 }
 
 #[test]
 fn match_mc_tree() {
     let structure = Arc::new(game::Structure::new(&LINES));
-    let mut white_player = ai::mc::MonteCarloAI::new(1000);
+    let mut white_player = ai::mc::MonteCarloAI::new(1000, Tactics::Off, Policy::Uniform);
     let mut black_player =
-        ai::tree::TreeJudgementAI::new(structure.clone(), 2, ai::value::Simple::Subsets);
+        ai::tree::TreeJudgementAI::new(2, ai::value::Simple::Subsets);
     run_match(structure, &mut white_player, &mut black_player);
 }
 
@@ -26,9 +29,9 @@ fn match_mc_tree() {
 fn match_tree() {
     let structure = Arc::new(game::Structure::new(&LINES));
     let mut white_player =
-        ai::tree::TreeJudgementAI::new(structure.clone(), 2, ai::value::Simple::Subsets);
+        ai::tree::TreeJudgementAI::new(2, ai::value::Simple::Subsets);
     let mut black_player =
-        ai::tree::TreeJudgementAI::new(structure.clone(), 2, ai::value::Simple::Subsets);
+        ai::tree::TreeJudgementAI::new(2, ai::value::Simple::Subsets);
     run_match(structure, &mut white_player, &mut black_player);
 }
 
@@ -49,3 +52,488 @@ fn subset_coherence() {
         }
     }
 }
+
+#[test]
+fn scripted_humans() {
+    use frontend::Scripted;
+    use game::Position2;
+    use replay::History;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    // White completes the row y = 0 while Black stacks up the row y = 1.
+    let script = vec![
+        Position2::new(0, 0),
+        Position2::new(0, 1),
+        Position2::new(1, 0),
+        Position2::new(1, 1),
+        Position2::new(2, 0),
+        Position2::new(2, 1),
+        Position2::new(3, 0),
+    ];
+    let frontend = Scripted::new(script);
+    let replay = ::humans(&frontend, History::new(structure));
+
+    assert_eq!(frontend.confirmed.borrow().len(), 7);
+    assert_eq!(Some(replay.state.victory_state), *frontend.result.borrow());
+    match replay.state.victory_state {
+        game::VictoryState::Win { winner, .. } => assert_eq!(winner, game::Color::White),
+        _ => panic!("White should have won this game."),
+    }
+}
+
+#[test]
+fn position_string_round_trip() {
+    let structure = Arc::new(game::Structure::new(&LINES));
+
+    let mut state = game::State::new(structure.clone());
+    assert_eq!(state.position_string(), "-/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- w");
+
+    for &column in &[0, 0, 5, 15, 0] {
+        state.execute(game::Position2(column));
+    }
+    let position = state.position_string();
+    assert_eq!(position, "wbw/-/-/-/-/w/-/-/-/-/-/-/-/-/-/b b");
+
+    let parsed = game::State::from_position_string(structure.clone(), &position).unwrap();
+    assert_eq!(&parsed.points[..], &state.points[..]);
+    assert_eq!(parsed.column_height, state.column_height);
+    assert_eq!(parsed.current_color, state.current_color);
+    assert_eq!(parsed.age, state.age);
+    assert_eq!(format!("{}", parsed), format!("{}", state));
+
+    // Wrong side to move, too many columns and unknown pieces are rejected.
+    let invalid = [
+        "w/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- w",
+        "-/-/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- w",
+        "x/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- b",
+    ];
+    for position in invalid.iter() {
+        assert!(game::State::from_position_string(structure.clone(), position).is_err());
+    }
+}
+
+#[test]
+fn position_string_detects_wins() {
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let position = "wwww/b/b/b/-/-/-/-/-/-/-/-/-/-/-/- b";
+    let state = game::State::from_position_string(structure, position).unwrap();
+    match state.victory_state {
+        game::VictoryState::Win { winner, reason } => {
+            assert_eq!(winner, game::Color::White);
+            assert!(reason.unwrap().contains(game::Position3::new(0, 0, 3)));
+        }
+        _ => panic!("The vertical line in A1 should be recognised."),
+    }
+}
+
+#[test]
+fn symmetries_of_structures() {
+    use constants::{PARALLELOGRAMS, PLUSSES};
+
+    // Only the eight rotations and reflections of the square preserve lines.
+    let lines = game::Structure::new(&LINES);
+    assert_eq!(lines.symmetries.len(), 8);
+
+    for source in &[&PARALLELOGRAMS[..], &PLUSSES[..]] {
+        let structure = game::Structure::new(source);
+        assert!(structure.symmetries.len() >= 8);
+        assert!(structure.symmetries.contains(&symmetry::Symmetry::identity()));
+    }
+}
+
+#[test]
+fn canonical_form_of_mirrored_positions() {
+    use symmetry::canonical_form;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let mut state = game::State::new(structure.clone());
+    let mut mirrored = game::State::new(structure.clone());
+
+    for &(x, y) in &[(0, 0), (1, 2), (0, 0), (3, 1)] {
+        state.execute(game::Position2::new(x, y));
+        mirrored.execute(game::Position2::new(3 - x, y));
+    }
+
+    let (canonical, symmetry) = canonical_form(&state);
+    let (canonical_mirrored, _) = canonical_form(&mirrored);
+    assert_eq!(canonical.position_string(), canonical_mirrored.position_string());
+    assert_eq!(
+        symmetry.inverse().apply_state(&canonical).position_string(),
+        state.position_string()
+    );
+}
+
+#[test]
+fn opening_book_uses_symmetries() {
+    use ai::book::OpeningBook;
+    use game::Position2;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let mut state = game::State::new(structure.clone());
+    state.execute(Position2::new(0, 0));
+
+    let mut book = OpeningBook::new();
+    book.insert(&state, Position2::new(1, 1));
+
+    // The mirror image of the position gets the mirrored answer.
+    let mut mirrored = game::State::new(structure.clone());
+    mirrored.execute(Position2::new(3, 0));
+    assert_eq!(book.lookup(&mirrored), Some(Position2::new(2, 1)));
+    assert_eq!(book.lookup(&game::State::new(structure.clone())), None);
+
+    let text = "# comment\nw/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- b B2\n";
+    let parsed = OpeningBook::parse(text, structure.clone()).unwrap();
+    assert_eq!(parsed.lookup(&mirrored), Some(Position2::new(2, 1)));
+    assert!(OpeningBook::parse("w/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- b E7", structure).is_err());
+}
+
+#[test]
+fn opening_book_validates_positions() {
+    use ai::book::OpeningBook;
+    use game::Position2;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+
+    // Entries for positions which aren't canonical are mirrored on loading.
+    let text = "-/-/-/w/-/-/-/-/-/-/-/-/-/-/-/- b C2";
+    let parsed = OpeningBook::parse(text, structure.clone()).unwrap();
+    let mut state = game::State::new(structure.clone());
+    state.execute(Position2::new(0, 0));
+    assert_eq!(parsed.lookup(&state), Some(Position2::new(1, 1)));
+
+    // Broken positions are reported with their line.
+    let error = OpeningBook::parse("# comment\nw/-/- b B2", structure.clone()).unwrap_err();
+    assert!(error.starts_with("Line 2 "));
+    assert!(OpeningBook::parse("w/-/-/-/-/-/-/-/-/-/-/-/-/-/-/- x B2", structure).is_err());
+}
+
+#[test]
+fn solver_finds_forced_wins() {
+    use ai::solver::{Outcome, Solver};
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+
+    // White can complete the first row at D1.
+    let position = "w/w/w/-/-/-/-/b/-/-/-/b/-/b/-/- w";
+    let state = game::State::from_position_string(structure.clone(), position).unwrap();
+    let (outcome, action) = Solver::new(&structure, 1000).solve(&state).unwrap();
+    assert_eq!(outcome, Outcome::Win(1));
+    assert_eq!(action, Some(game::Position2::new(3, 0)));
+
+    // White threatens both D1 and A4, Black can only block one of them.
+    let position = "w/w/w/-/w/-/-/b/w/-/-/b/-/b/b/- b";
+    let state = game::State::from_position_string(structure.clone(), position).unwrap();
+    let (outcome, _) = Solver::new(&structure, 100000).solve(&state).unwrap();
+    assert_eq!(outcome, Outcome::Loss(2));
+}
+
+#[test]
+fn tactics_win_and_block() {
+    use ai::StatelessAI;
+    use ai::random::RandomSogoAI;
+    use game::Position2;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let random = RandomSogoAI::new(Tactics::Root);
+
+    // White can complete the first row at D1.
+    let position = "w/w/w/-/-/-/-/b/-/-/-/b/-/b/-/- w";
+    let state = game::State::from_position_string(structure.clone(), position).unwrap();
+    assert_eq!(random.action(&state), Position2::new(3, 0));
+
+    // Black has to block D1.
+    let position = "w/w/w/-/-/-/-/b/-/-/-/b/-/-/-/- b";
+    let state = game::State::from_position_string(structure.clone(), position).unwrap();
+    assert_eq!(random.action(&state), Position2::new(3, 0));
+}
+
+#[test]
+fn playout_policies() {
+    use ai::mc::random_playout;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    // White can complete the first row at D1.
+    let position = "w/w/w/-/-/-/-/b/-/-/-/b/-/b/-/- w";
+    let state = game::State::from_position_string(structure.clone(), position).unwrap();
+
+    for _ in 0..20 {
+        let result = random_playout(state.clone(), Policy::Uniform, false);
+        assert!(result != game::VictoryState::Undecided);
+        for &policy in &[Policy::WinOrBlock, Policy::Heuristic] {
+            let result = random_playout(state.clone(), policy, false);
+            assert_eq!(result.scoring(game::Color::White), Some(1));
+        }
+        // Heavy playouts win regardless of the policy.
+        let result = random_playout(state.clone(), Policy::Uniform, true);
+        assert_eq!(result.scoring(game::Color::White), Some(1));
+    }
+}
+
+#[test]
+fn mctree_enhancements() {
+    use ai::mctree::Enhancements;
+
+    let mut enhancements = Enhancements::default();
+    for token in &["rave", "bias=0.5", "widening"] {
+        enhancements.enable(token).unwrap();
+    }
+    assert_eq!(enhancements.rave, Some(300.0));
+    assert_eq!(enhancements.bias, Some(0.5));
+    assert_eq!(enhancements.widening, Some(1.0));
+    assert!(enhancements.enable("bias=much").is_err());
+    assert!(enhancements.enable("magic").is_err());
+}
+
+#[test]
+fn mctree_solver() {
+    use ai::StatelessAI;
+    use ai::mctree::{Enhancements, MCTreeAI};
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let ai = MCTreeAI::new(5000, 1.41, Tactics::Off, Policy::Uniform, Enhancements::default());
+
+    // White can complete the first row at D1.
+    let position = "w/w/w/-/-/-/-/b/-/-/-/b/-/b/-/- w";
+    let state = game::State::from_position_string(structure.clone(), position).unwrap();
+    assert_eq!(ai.action(&state), game::Position2::new(3, 0));
+
+    let analysis = ai.analyse(&state);
+    assert_eq!(analysis.best, game::Position2::new(3, 0));
+    assert_eq!(analysis.overlay()[3], Some(1.0));
+    for stats in &analysis.columns {
+        assert_eq!(stats.principal_variation[0], stats.column);
+    }
+
+    // Every other action loses for Black.
+    let position = "w/w/w/-/-/-/-/b/-/-/-/b/-/-/-/- b";
+    let state = game::State::from_position_string(structure, position).unwrap();
+    assert_eq!(ai.action(&state), game::Position2::new(3, 0));
+}
+
+#[test]
+fn mctree_draw_is_not_proven() {
+    use ai::mctree::{Enhancements, MCTreeAI};
+
+    // Only A3 is left and the game ends in a draw.
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let position = "wbww/bwbb/wwwb/bwwb/wwbw/wbbb/wbbw/bwww/bwb/bwwb/bwbb/wbbw/wbwb/wbbw/bwbw/wbwb b";
+    let state = game::State::from_position_string(structure, position).unwrap();
+    let mut drawn = state.clone();
+    drawn.execute(game::Position2::new(0, 2));
+    assert_eq!(drawn.victory_state, game::VictoryState::Draw);
+
+    let ai = MCTreeAI::new(100, 1.41, Tactics::Off, Policy::Uniform, Enhancements::default());
+    let analysis = ai.analyse(&state);
+    assert_eq!(analysis.best, game::Position2::new(0, 2));
+    assert_eq!(analysis.overlay()[8], Some(0.0));
+}
+
+#[test]
+fn hud_describes_position() {
+    use game::Position2;
+    use game_view;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let mut view_state = game_view::State::from_state(game::State::new(structure));
+    view_state.replay.add(Position2::new(1, 2));
+    view_state.replay.add(Position2::new(1, 2));
+
+    let hud = game_view::hud(&view_state);
+    assert_eq!(hud[0], "Move 3, White to move");
+    assert_eq!(hud[1], "Last move: B3 (2)");
+    assert_eq!(hud[2], "AI thinking...");
+
+    view_state.replay.back().unwrap();
+    assert_eq!(view_state.replay.playback_progress(), Some((1, 2)));
+    assert_eq!(
+        view_state.replay.last_placement(),
+        Some(game::Position3::new(1, 2, 0))
+    );
+    assert!(game_view::hud(&view_state)[2].starts_with("Playback 1/2"));
+}
+
+#[test]
+fn banner_without_font() {
+    use game::Position2;
+    use game_view;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let mut view_state = game_view::State::from_state(game::State::new(structure));
+    assert_eq!(game_view::banner(&view_state), None);
+
+    // White completes the first row while Black stacks on top of it.
+    for &x in &[0, 0, 1, 1, 2, 2, 3] {
+        view_state.replay.add(Position2::new(x, 0));
+    }
+    let text = game_view::plain_text(&view_state);
+    assert!(text.contains("Last move: D1 (1)\n"));
+    assert!(text.ends_with("\nWhite has won!"));
+}
+
+#[test]
+fn structure_browser() {
+    use game::Position2;
+    use game_view;
+    use game_view::{Browser, ViewMode};
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let mut view_state = game_view::State::from_state(game::State::new(structure.clone()));
+    let mut browser = Browser {
+        index: 0,
+        hovered: None,
+    };
+
+    // Stepping wraps around in both directions.
+    browser.step(&structure, -1);
+    assert_eq!(browser.index, 75);
+    browser.step(&structure, 1);
+    assert_eq!(browser.index, 0);
+    assert_eq!(browser.subsets(&structure), vec![structure.source[0]]);
+    view_state.browser = Some(browser);
+    assert_eq!(
+        game_view::hud(&view_state).last().unwrap(),
+        "Subset 1/76, press N / P to browse"
+    );
+
+    // A corner is part of seven lines.
+    browser.hovered = Some(game_view::browsed_cell(&view_state, Position2::new(0, 0)));
+    assert_eq!(browser.subsets(&structure).len(), 7);
+    view_state.browser = Some(browser);
+    assert_eq!(game_view::hud(&view_state).last().unwrap(), "7 subsets through A1 (1)");
+
+    // The hovered cell is the landing cell, or lies in the displayed layer.
+    view_state.replay.add(Position2::new(1, 1));
+    assert_eq!(
+        game_view::browsed_cell(&view_state, Position2::new(1, 1)),
+        game::Position3::new(1, 1, 1)
+    );
+    view_state.view_mode = ViewMode::Slice(3);
+    assert_eq!(
+        game_view::browsed_cell(&view_state, Position2::new(1, 1)),
+        game::Position3::new(1, 1, 3)
+    );
+}
+
+#[test]
+fn view_modes() {
+    use game_view;
+    use game_view::ViewMode;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let mut view_state = game_view::State::from_state(game::State::new(structure));
+    assert_eq!(view_state.view_mode, ViewMode::Solid);
+    let solid_hud = game_view::hud(&view_state);
+
+    let expected = [
+        (ViewMode::Exploded, "View: exploded layers"),
+        (ViewMode::SeeThrough, "View: see-through"),
+        (ViewMode::Slice(0), "View: layer 1 only"),
+    ];
+    for &(mode, description) in &expected {
+        view_state.view_mode = view_state.view_mode.next();
+        assert_eq!(view_state.view_mode, mode);
+        assert_eq!(game_view::hud(&view_state).last().unwrap(), description);
+    }
+    view_state.view_mode = view_state.view_mode.next();
+    assert_eq!(view_state.view_mode, ViewMode::Solid);
+    assert_eq!(game_view::hud(&view_state), solid_hud);
+}
+
+#[test]
+fn diagram_shows_position() {
+    use diagram;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let position = "w/w/w/w/b/b/b/-/-/-/-/-/-/-/-/- b";
+    let state = game::State::from_position_string(structure, position).unwrap();
+    let winner = diagram::winning_subset(&state);
+    assert!(winner.is_some());
+
+    let svg = diagram::svg(&state, winner);
+    assert!(svg.starts_with("<svg"));
+    assert_eq!(svg.matches("<circle").count(), 7);
+    assert_eq!(svg.matches("#ffd84d").count(), 4);
+}
+
+#[test]
+fn notation_round_trip() {
+    use diagram;
+    use replay::History;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let notation = "1. A1 (1) 2. A2 (1) 3. B1 (1) 4. B2 (1) 5. C1 (1) 6. C2 (1) 7. D1 (1) ";
+    let history = History::from_notation(game::State::new(structure.clone()), notation).unwrap();
+    assert_eq!(history.notation(), notation);
+    assert!(!history.state.victory_state.active());
+
+    let svg = diagram::animated_svg(&history);
+    assert_eq!(svg.matches("<circle").count(), 7);
+    assert_eq!(svg.matches("<animate").count(), 11);
+
+    let start = || game::State::new(structure.clone());
+    assert!(History::from_notation(start(), "1. A1 (2)").is_err());
+    assert!(History::from_notation(start(), "2. A1").is_err());
+    assert!(History::from_notation(start(), "A1 A1 A1 A1 A1").is_err());
+    assert!(History::from_notation(start(), &(notation.to_owned() + "8. D4")).is_err());
+}
+
+#[test]
+fn custom_structure_file() {
+    use constants::StructureSource;
+    use std::fs::File;
+    use std::io::Write;
+
+    assert!(game::Structure::try_new(&[]).is_err());
+    assert!(game::Structure::try_new(&[0]).is_err());
+    assert!(game::Structure::try_new(&[3, 3]).is_err());
+    assert!(game::Structure::try_new(&[3, 7]).is_err());
+
+    // The four space diagonals, once as coordinates and once as a mask.
+    let path = ::std::env::temp_dir().join("sogo_custom_structure.txt");
+    let mut file = File::create(&path).unwrap();
+    writeln!(file, "# Space diagonals").unwrap();
+    writeln!(file, "0,0,0 1,1,1 2,2,2 3,3,3").unwrap();
+    writeln!(file, "3,0,0 2,1,1 1,2,2 0,3,3").unwrap();
+    writeln!(file, "0,3,0 1,2,1 2,1,2 3,0,3").unwrap();
+    writeln!(file, "0x{:x}", (1u64 << 15) | (1 << 26) | (1 << 37) | (1 << 48)).unwrap();
+    drop(file);
+
+    let source = path.to_str().unwrap().parse::<StructureSource>().unwrap();
+    let structure: game::Structure = source.into();
+    assert_eq!(structure.source.len(), 4);
+    assert_eq!(structure.object_size, 4);
+    assert!(LINES.contains(&structure.source[3].0));
+
+    let mut file = File::create(&path).unwrap();
+    writeln!(file, "0,0,0 1,1,1 2,2,4").unwrap();
+    drop(file);
+    assert!(path.to_str().unwrap().parse::<StructureSource>().is_err());
+}
+
+#[test]
+fn generator_matches_tables() {
+    use constants::{PARALLELOGRAMS, PLUSSES};
+    use generator;
+    use generator::Symmetries;
+    use std::collections::HashSet;
+
+    let check = |templates: Vec<Vec<generator::Offset>>, table: &[u64]| {
+        let generated = generator::generate(&templates, Symmetries::Cube);
+        assert_eq!(generated.len(), table.len());
+        let generated: HashSet<u64> = generated.into_iter().collect();
+        let table: HashSet<u64> = table.iter().cloned().collect();
+        assert_eq!(generated, table);
+    };
+    check(generator::lines(), &LINES);
+    check(generator::parallelograms(), &PARALLELOGRAMS);
+    check(generator::plusses(), &PLUSSES);
+
+    // Without rotations, horizontal rows along x are all that's left.
+    let row = vec![(0, 0, 0), (1, 0, 0), (2, 0, 0), (3, 0, 0)];
+    let rows = generator::structure(&[row], Symmetries::Translations).unwrap();
+    assert_eq!(rows.source.len(), 16);
+    // Rows along x and y, diagonals of the layers and space diagonals, but
+    // nothing else which goes up.
+    let horizontal = generator::structure(&generator::lines(), Symmetries::Horizontal).unwrap();
+    assert_eq!(horizontal.source.len(), 32 + 8 + 4);
+}
@@ -23,4 +23,5 @@ pub enum UiEvent {
         action: Position2,
         color: game::Color,
     },
+    Overlay(Option<[Option<f32>; 16]>),
 }
@@ -0,0 +1,130 @@
+/* Optimises the parameters of the weighted value function by self-play.
+
+This is a simple local search: In each iteration one parameter of the best
+known weights is changed by a random amount. The changed weights then play a
+short match against the best weights, using the tree AI for both sides. If the
+challenger wins more games than it loses, it becomes the new best. */
+
+use rand::{thread_rng, Rng};
+
+use ai;
+use ai::tree::TreeJudgementAI;
+use ai::value::{Simple, Weights};
+use game;
+
+pub struct Settings {
+    // Search depth of the tree AIs.
+    pub depth: u8,
+    pub iterations: usize,
+    // Games per iteration, the colors alternate between games.
+    pub games: usize,
+    // The largest change to a parameter in one iteration.
+    pub step: i32,
+    // The best weights are written here whenever they improve.
+    pub output: String,
+}
+
+pub fn tune(start: &game::State, initial: Weights, settings: &Settings) -> Weights {
+    let mut rng = thread_rng();
+    let mut best = initial;
+
+    // The win value only sets the scale, so it is left alone.
+    let names: Vec<&str> = Weights::NAMES
+        .iter()
+        .cloned()
+        .filter(|name| *name != "win")
+        .collect();
+
+    for iteration in 1..settings.iterations + 1 {
+        let name = *rng.choose(&names).unwrap();
+        let mut delta = rng.gen_range(1, settings.step + 1);
+        if rng.gen() {
+            delta = -delta;
+        }
+
+        let mut challenger = best;
+        *challenger.get_mut(name).unwrap() += delta;
+
+        let score = duel(start, challenger, best, settings);
+        let accepted = score > 0;
+        if accepted {
+            best = challenger;
+            if let Err(err) = best.save(&settings.output) {
+                println!("{}", err);
+            }
+        }
+
+        println!(
+            "Iteration {}: {} {:+} scored {:+} in {} games, {}.",
+            iteration,
+            name,
+            delta,
+            score,
+            settings.games,
+            if accepted { "accepted" } else { "rejected" }
+        );
+    }
+
+    best
+}
+
+// Returns the number of games won by the challenger minus the games it lost.
+fn duel(start: &game::State, challenger: Weights, champion: Weights, settings: &Settings) -> i32 {
+    let mut challenger_ai = TreeJudgementAI::new(settings.depth, Simple::Weighted(challenger));
+    let mut champion_ai = TreeJudgementAI::new(settings.depth, Simple::Weighted(champion));
+    let mut score = 0;
+
+    for game in 0..settings.games {
+        let (result, challenger_color) = if game % 2 == 0 {
+            let result = ai::run_match_from(start.clone(), &mut challenger_ai, &mut champion_ai);
+            (result, game::Color::White)
+        } else {
+            let result = ai::run_match_from(start.clone(), &mut champion_ai, &mut challenger_ai);
+            (result, game::Color::Black)
+        };
+        score += result.victory_state.scoring(challenger_color).unwrap() as i32;
+    }
+
+    score
+}
+
+#[test]
+fn test_tune_smoke() {
+    use constants::LINES;
+    use std::fs;
+    use std::sync::Arc;
+
+    let structure = Arc::new(game::Structure::new(&LINES));
+    let start = game::State::new(structure);
+    // Parallel test runs must not share the file.
+    let file_name = format!("sogo_tune_smoke_{}.params", thread_rng().gen::<u32>());
+    let output = ::std::env::temp_dir().join(file_name);
+    let output = output.to_str().unwrap().to_owned();
+    let settings = Settings {
+        depth: 1,
+        iterations: 3,
+        games: 1,
+        step: 2,
+        output: output.clone(),
+    };
+
+    // The file is only written on improvements, so it starts out with the
+    // initial weights.
+    Weights::default().save(&output).unwrap();
+    let tuned = tune(&start, Weights::default(), &settings);
+    let saved = Weights::load(&output);
+    fs::remove_file(&output).unwrap();
+    assert_eq!(saved, Ok(tuned));
+
+    // Each iteration changes at most one parameter by at most one step, the
+    // win value stays fixed.
+    let changes: Vec<i32> = Weights::NAMES
+        .iter()
+        .map(|name| tuned.get(name).unwrap() - Weights::default().get(name).unwrap())
+        .filter(|&change| change != 0)
+        .collect();
+    assert!(changes.len() <= settings.iterations);
+    let total: i32 = changes.iter().map(|change| change.abs()).sum();
+    assert!(total <= settings.step * settings.iterations as i32);
+    assert_eq!(tuned.win, Weights::default().win);
+}
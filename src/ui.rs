@@ -1,14 +1,20 @@
 
 use game_view;
-use game_view::Phase;
+use game_view::{Phase, ViewMode};
+use frontend::Frontend;
+use ai;
 use game;
 use game::Position2;
 use thread_synchronisation::{CoreEvent, UiEvent};
-use std::sync::Arc;
+
+use std::env;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
 
 // Thread Communication
 use std::thread;
-use std::sync::mpsc::{channel, Sender, Receiver};
+use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError};
 
 use na::Point3;
 use glfw;
@@ -18,6 +24,17 @@ use glfw::{Action, MouseButton, WindowEvent, Key};
 use kiss3d::window::Window;
 use kiss3d::light::Light;
 use kiss3d::camera::ArcBall;
+use kiss3d::text::Font;
+
+// Fonts are not bundled, so text is only shown if one of these exists.
+// The SOGO_FONT environment variable takes precedence. Without a font, the
+// text is printed to the terminal instead.
+const FONT_PATHS: [&str; 4] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
 
 
 fn prepare_window() -> Window {
@@ -29,6 +46,25 @@ fn prepare_window() -> Window {
     window
 }
 
+fn load_font() -> Option<Rc<Font>> {
+    let custom = env::var("SOGO_FONT").ok();
+    let path = custom
+        .iter()
+        .map(|path| path.as_str())
+        .chain(FONT_PATHS.iter().cloned())
+        .find(|path| Path::new(path).is_file());
+    match path {
+        Some(path) => Some(Font::new(Path::new(path), 40)),
+        None => {
+            println!(
+                "No font found, set SOGO_FONT to a TrueType font to show text in the \
+                 window. The text is printed here instead."
+            );
+            None
+        }
+    }
+}
+
 fn prepare_camera() -> ArcBall {
     let position = Point3::new(6.0f32, 6.0, 6.0);
     let looking_towards = Point3::new(0.0f32, 1.5, 0.0);
@@ -43,6 +79,24 @@ fn prepare_camera() -> ArcBall {
 }
 
 
+// Evaluates the position on a background thread, so the window stays
+// responsive. The result arrives on the returned channel, unless the
+// evaluator can't analyse positions.
+fn spawn_evaluation(
+    state: game::State,
+    evaluator: ai::Constructor,
+) -> Receiver<[Option<f32>; 16]> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        if let Some(analysis) = ai::AIBox::new(evaluator).analyse(&state) {
+            // The window may be gone before the evaluation finishes.
+            let _ = sender.send(analysis.overlay());
+        }
+    });
+    receiver
+}
+
+
 pub struct UiConnector {
     // A UiEvent is send TO the ui, a core event is send back.
     sender: Sender<UiEvent>,
@@ -51,12 +105,12 @@ pub struct UiConnector {
 
 
 impl UiConnector {
-    pub fn new(structure: Arc<game::Structure>) -> Self {
+    pub fn new(start: game::State, evaluator: ai::Constructor) -> Self {
         let (my_sender, thread_receiver) = channel();
         let (thread_sender, my_receiver) = channel();
 
         thread::spawn(move || {
-            run_ui(thread_sender, thread_receiver, structure);
+            run_ui(thread_sender, thread_receiver, start, evaluator);
         });
 
         UiConnector {
@@ -64,7 +118,10 @@ impl UiConnector {
             receiver: my_receiver,
         }
     }
-    pub fn wait_for_action(&self) -> Result<Position2, String> {
+}
+
+impl Frontend for UiConnector {
+    fn wait_for_action(&self) -> Result<Position2, String> {
         self.sender.send(UiEvent::StartTurn).unwrap();
 
         // Blocks the thread until the user submits an action or quits.
@@ -81,7 +138,7 @@ impl UiConnector {
             Err("Application window closed.".to_owned())
         }
     }
-    pub fn confirmed_action(&self, action: Position2, color: game::Color) -> Result<(), String> {
+    fn confirmed_action(&self, action: Position2, color: game::Color) -> Result<(), String> {
         self.sender
             .send(UiEvent::RenderAction {
                 action: action,
@@ -90,10 +147,13 @@ impl UiConnector {
             .unwrap();
         Ok(())
     }
-    pub fn game_over(&self, victory_state: game::VictoryState) {
+    fn game_over(&self, victory_state: game::VictoryState) {
         self.sender.send(UiEvent::GameOver(victory_state)).unwrap();
     }
-    pub fn wait_for_halt(&self) {
+    fn show_overlay(&self, overlay: Option<[Option<f32>; 16]>) {
+        self.sender.send(UiEvent::Overlay(overlay)).unwrap();
+    }
+    fn wait_for_halt(&self) {
         // Blocks the thread until the user submits an action or quits.
         if let Ok(event) = self.receiver.recv() {
             match event {
@@ -117,12 +177,21 @@ impl UiConnector {
 pub fn run_ui(
     core_sender: Sender<CoreEvent>,
     ui_receiver: Receiver<UiEvent>,
-    structure: Arc<game::Structure>,
+    start: game::State,
+    evaluator: ai::Constructor,
 ) {
-    let mut view_state = game_view::State::empty(structure.clone());
+    let mut view_state = game_view::State::from_state(start);
 
     let mut window = prepare_window();
     let mut camera = prepare_camera();
+    let font = load_font();
+    let mut printed_text = String::new();
+    let mut scene = game_view::Scene::new(window.scene_mut(), &view_state);
+    let mut last_frame = Instant::now();
+
+    // The position which was evaluated last and a running evaluation.
+    let mut evaluated_position: Option<String> = None;
+    let mut evaluation_task: Option<(String, Receiver<[Option<f32>; 16]>)> = None;
 
     while window.render_with_camera(&mut camera) {
         // Read the inter thread communication channel
@@ -131,6 +200,9 @@ pub fn run_ui(
                 UiEvent::RenderAction { action, .. } => {
                     view_state.replay.add(action);
                 }
+                UiEvent::Overlay(overlay) => {
+                    view_state.overlay = overlay;
+                }
                 UiEvent::StartTurn => {
                     view_state.phase = Phase::Input;
                 }
@@ -145,6 +217,11 @@ pub fn run_ui(
         for event in window.events().iter() {
             match event.value {
                 WindowEvent::CursorPos(x, y) => {
+                    if let Some(mut browser) = view_state.browser {
+                        browser.hovered = game_view::placement_coordinate(&window, &camera, (x, y))
+                            .map(|column| game_view::browsed_cell(&view_state, column));
+                        view_state.browser = Some(browser);
+                    }
                     if view_state.phase == Phase::Input && view_state.replay.is_resumed() {
                         let mut placement_candidate =
                             game_view::placement_coordinate(&window, &camera, (x, y));
@@ -157,21 +234,7 @@ pub fn run_ui(
                     }
                 }
                 WindowEvent::MouseButton(MouseButton::Button1, Action::Release, _) => {
-                    if let Some(position) = view_state.hint {
-                        assert_eq!(view_state.phase, Phase::Input);
-                        view_state.hint = None;
-                        // Is placing a piece allowed?
-                        if view_state.replay.state.column_height[position.0 as usize] <= 3 {
-                            core_sender
-                                .send(CoreEvent::Action {
-                                    action: position,
-                                    color: view_state.replay.state.current_color,
-                                })
-                                .unwrap();
-                        }
-                        view_state.phase = Phase::Waiting;
-
-                    }
+                    submit_hint(&mut view_state, &core_sender);
                 }
                 WindowEvent::Key(Key::Left, _, Action::Release, _) => {
                     let result = view_state.replay.back();
@@ -190,11 +253,153 @@ pub fn run_ui(
                 WindowEvent::Key(Key::Space, _, Action::Release, _) => {
                     view_state.replay.resume();
                 }
+                WindowEvent::Key(Key::E, _, Action::Release, _) => {
+                    view_state.show_evaluation = !view_state.show_evaluation;
+                }
+                WindowEvent::Key(Key::S, _, Action::Release, _) => {
+                    view_state.browser = match view_state.browser {
+                        Some(_) => None,
+                        None => Some(game_view::Browser {
+                            index: 0,
+                            hovered: None,
+                        }),
+                    };
+                }
+                WindowEvent::Key(Key::N, _, Action::Release, _) => {
+                    if let Some(ref mut browser) = view_state.browser {
+                        browser.step(&view_state.replay.state.structure, 1);
+                    }
+                }
+                WindowEvent::Key(Key::P, _, Action::Release, _) => {
+                    if let Some(ref mut browser) = view_state.browser {
+                        browser.step(&view_state.replay.state.structure, -1);
+                    }
+                }
+                WindowEvent::Key(Key::V, _, Action::Release, _) => {
+                    view_state.view_mode = view_state.view_mode.next();
+                }
+                WindowEvent::Key(Key::Up, _, Action::Release, _) => {
+                    if let ViewMode::Slice(layer) = view_state.view_mode {
+                        view_state.view_mode = ViewMode::Slice(if layer < 3 { layer + 1 } else { 3 });
+                    }
+                }
+                WindowEvent::Key(Key::Down, _, Action::Release, _) => {
+                    if let ViewMode::Slice(layer) = view_state.view_mode {
+                        view_state.view_mode = ViewMode::Slice(if layer > 0 { layer - 1 } else { 0 });
+                    }
+                }
+                WindowEvent::Key(key, _, Action::Release, _) => {
+                    keyboard_input(&mut view_state, key, &core_sender);
+                }
                 _ => {}
             }
         }
 
-        game_view::render(window.scene_mut(), &view_state);
+        if view_state.show_evaluation {
+            let position = view_state.replay.state.position_string();
+
+            if let Some((task_position, receiver)) = evaluation_task.take() {
+                match receiver.try_recv() {
+                    Ok(evaluation) => {
+                        if task_position == position {
+                            view_state.evaluation = Some(evaluation);
+                        }
+                        evaluated_position = Some(task_position);
+                    }
+                    Err(TryRecvError::Empty) => evaluation_task = Some((task_position, receiver)),
+                    Err(TryRecvError::Disconnected) => {}
+                }
+            }
+
+            if evaluated_position.as_ref() != Some(&position) {
+                view_state.evaluation = None;
+                if evaluation_task.is_none() && view_state.replay.state.victory_state.active() {
+                    let receiver = spawn_evaluation(view_state.replay.state.clone(), evaluator.clone());
+                    evaluation_task = Some((position, receiver));
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(last_frame);
+        last_frame = now;
+        let seconds = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+        scene.update(&view_state, seconds);
+
+        match font {
+            Some(ref font) => game_view::render_text(&mut window, font, &view_state),
+            None => {
+                let text = game_view::plain_text(&view_state);
+                if text != printed_text {
+                    println!("{}", text);
+                    printed_text = text;
+                }
+            }
+        }
     }
     core_sender.send(CoreEvent::Halt).unwrap();
 }
+
+// Sends the hinted action to the core, if there is one.
+fn submit_hint(view_state: &mut game_view::State, core_sender: &Sender<CoreEvent>) {
+    if let Some(position) = view_state.hint {
+        assert_eq!(view_state.phase, Phase::Input);
+        view_state.hint = None;
+        // Is placing a piece allowed?
+        if view_state.replay.state.column_height[position.0 as usize] <= 3 {
+            core_sender
+                .send(CoreEvent::Action {
+                    action: position,
+                    color: view_state.replay.state.current_color,
+                })
+                .unwrap();
+        }
+        view_state.phase = Phase::Waiting;
+
+    }
+}
+
+// Moves the keyboard cursor. Full columns can't be hinted.
+fn select_column(view_state: &mut game_view::State, column: Position2) {
+    view_state.cursor = column;
+    view_state.hint = if view_state.replay.state.column_full(column) {
+        None
+    } else {
+        Some(column)
+    };
+}
+
+// Columns are selected by typing their name (e.g. B then 3) or by moving the
+// cursor with I, J, K and L. Enter places the piece.
+fn keyboard_input(view_state: &mut game_view::State, key: Key, core_sender: &Sender<CoreEvent>) {
+    if view_state.phase != Phase::Input || !view_state.replay.is_resumed() {
+        return;
+    }
+    let (x, y) = view_state.cursor.coords();
+
+    match key {
+        Key::A => view_state.typed_letter = Some('A'),
+        Key::B => view_state.typed_letter = Some('B'),
+        Key::C => view_state.typed_letter = Some('C'),
+        Key::D => view_state.typed_letter = Some('D'),
+        Key::Num1 | Key::Num2 | Key::Num3 | Key::Num4 => {
+            let digit = match key {
+                Key::Num1 => '1',
+                Key::Num2 => '2',
+                Key::Num3 => '3',
+                _ => '4',
+            };
+            if let Some(letter) = view_state.typed_letter.take() {
+                if let Ok(column) = format!("{}{}", letter, digit).parse::<Position2>() {
+                    select_column(view_state, column);
+                }
+            }
+        }
+        Key::I => select_column(view_state, Position2::new(x, if y < 3 { y + 1 } else { 3 })),
+        Key::K => select_column(view_state, Position2::new(x, if y > 0 { y - 1 } else { 0 })),
+        Key::J => select_column(view_state, Position2::new(if x > 0 { x - 1 } else { 0 }, y)),
+        Key::L => select_column(view_state, Position2::new(if x < 3 { x + 1 } else { 3 }, y)),
+        Key::Enter => submit_hint(view_state, core_sender),
+        _ => {}
+    }
+}